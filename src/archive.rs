@@ -0,0 +1,414 @@
+//! Unpacking packages from `.tar.gz`, `.tar.xz` and `.zip` archives.
+//!
+//! Archives are untrusted input: every entry has to stay inside the directory
+//! it is unpacked into, both by its own path and, for symlinks, by where it
+//! points to. Archives that break this are rejected as a whole.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use flate2::read::GzDecoder;
+use tar::EntryType;
+use xz2::read::XzDecoder;
+use zip::ZipArchive;
+
+use crate::error::Error;
+
+/// The archive formats packages can be installed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    TarGz,
+    TarXz,
+    Zip,
+}
+
+impl Format {
+    /// The format of the archive at `path`, based on its file name.
+    pub fn of(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(Format::TarXz)
+        } else if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Unpacks the `archive` into the new directory `dest` and returns the
+/// directory the package(s) are in.
+///
+/// That is `dest` itself, unless the archive holds everything in a single
+/// top-level folder without a `typst.toml` next to it, in which case it is that
+/// folder.
+///
+/// # Errors
+///
+/// When the archive can not be read or one of its entries would end up outside
+/// of `dest`.
+pub fn unpack(archive: &Path, format: Format, dest: &Path) -> anyhow::Result<PathBuf> {
+    let file = File::open(archive).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => {
+            Error::NotFound(format!("{} does not exist", archive.display()))
+        }
+        _ => Error::Io(format!("failed to read {}: {err}", archive.display())),
+    })?;
+
+    fs::create_dir(dest)
+        .with_context(|| format!("failed to create {}", dest.display()))?;
+
+    match format {
+        Format::TarGz => unpack_tar(GzDecoder::new(file), dest),
+        Format::TarXz => unpack_tar(XzDecoder::new(file), dest),
+        Format::Zip => unpack_zip(file, dest),
+    }
+    .map_err(|err| match err.is::<Error>() {
+        true => err,
+        false => {
+            let message = format!("failed to unpack {}: {err}", archive.display());
+            err.context(message)
+        }
+    })?;
+
+    if dest.join("typst.toml").exists() {
+        return Ok(dest.to_path_buf());
+    }
+
+    let entries: Vec<_> = fs::read_dir(dest)?.collect::<Result<_, _>>()?;
+    match entries.as_slice() {
+        [entry] if entry.file_type()?.is_dir() => Ok(entry.path()),
+        _ => Ok(dest.to_path_buf()),
+    }
+}
+
+/// Unpacks a tar archive into `dest`.
+fn unpack_tar<R: Read>(reader: R, dest: &Path) -> anyhow::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let target = prepare(dest, &path)?;
+
+        match entry.header().entry_type() {
+            EntryType::Directory => fs::create_dir_all(&target)?,
+            EntryType::Regular | EntryType::Continuous => {
+                io::copy(&mut entry, &mut File::create(&target)?)?;
+            }
+            EntryType::Symlink => {
+                let link = entry.link_name()?.unwrap_or_default().into_owned();
+                symlink(dest, &path, &link)?;
+            }
+            EntryType::Link => {
+                // Hard links point at another entry of the archive, copy it.
+                let link = entry.link_name()?.unwrap_or_default().into_owned();
+                if !is_relative(&link) || !dest.join(&link).is_file() {
+                    bail!(Error::InvalidPackage(format!(
+                        "{} links to {}, which is not a file in the archive",
+                        path.display(),
+                        link.display()
+                    )));
+                }
+
+                fs::copy(dest.join(&link), &target)?;
+            }
+            // Devices, fifos and metadata entries have no place in a package.
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Unpacks a zip archive into `dest`.
+fn unpack_zip(file: File, dest: &Path) -> anyhow::Result<()> {
+    let mut archive = ZipArchive::new(file)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let path = PathBuf::from(entry.name());
+        let target = prepare(dest, &path)?;
+
+        // Zip archives made on Unix store symlinks as files holding their target.
+        let is_symlink =
+            entry.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+
+        if entry.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if is_symlink {
+            let mut link = String::new();
+            entry.read_to_string(&mut link)?;
+            symlink(dest, &path, Path::new(&link))?;
+        } else {
+            io::copy(&mut entry, &mut File::create(&target)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes room for the entry at `path`, relative to the archive root, in `dest`
+/// and returns where it goes.
+///
+/// # Errors
+///
+/// When `path` would end up outside of `dest`, either because it is not a plain
+/// relative path or because it is inside a symlink unpacked earlier.
+fn prepare(dest: &Path, path: &Path) -> anyhow::Result<PathBuf> {
+    if !is_relative(path) {
+        bail!(Error::InvalidPackage(format!(
+            "archive entry {} points outside of the archive",
+            path.display()
+        )));
+    }
+
+    // Never write through a symlink, not even one that points inside `dest`.
+    let mut ancestor = dest.to_path_buf();
+    let mut components = path.components().peekable();
+    while let Some(component) = components.next() {
+        ancestor.push(component);
+        if components.peek().is_none() {
+            break;
+        }
+
+        match ancestor.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                bail!(Error::InvalidPackage(format!(
+                    "archive entry {} is inside a symlink",
+                    path.display()
+                )));
+            }
+            Ok(_) => {}
+            // Nothing below a missing directory exists either.
+            Err(_) => break,
+        }
+    }
+
+    let target = dest.join(path);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if target.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()) {
+        fs::remove_file(&target)?;
+    }
+
+    Ok(target)
+}
+
+/// Creates the symlink at `path`, relative to the archive root, in `dest` that
+/// points to `link`.
+///
+/// # Errors
+///
+/// When `path` is the archive root itself or `link` would point outside of
+/// `dest`. Only leading `..` components are allowed, so a link can not climb out
+/// through another symlink.
+fn symlink(dest: &Path, path: &Path, link: &Path) -> anyhow::Result<()> {
+    let Some(depth) = path
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .count()
+        .checked_sub(1)
+    else {
+        bail!(Error::InvalidPackage(format!(
+            "archive entry {} is a symlink in place of the archive root",
+            path.display()
+        )));
+    };
+    let ups = link.components().take_while(|c| *c == Component::ParentDir).count();
+    let rest_is_relative = link
+        .components()
+        .skip(ups)
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    if ups > depth || !rest_is_relative {
+        bail!(Error::InvalidPackage(format!(
+            "archive entry {} links to {}, outside of the archive",
+            path.display(),
+            link.display()
+        )));
+    }
+
+    let target = dest.join(path);
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(link, &target)?;
+
+    // Creating symlinks needs extra privileges on Windows, copy the target
+    // instead if it was unpacked already.
+    #[cfg(windows)]
+    if target.parent().unwrap().join(link).is_file() {
+        fs::copy(target.parent().unwrap().join(link), &target)?;
+    }
+
+    Ok(())
+}
+
+/// Whether `path` is a plain relative path that stays below where it starts.
+fn is_relative(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{write::GzEncoder, Compression};
+    use tempfile::TempDir;
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    /// An entry of a test archive. Paths are written as is, without the checks
+    /// archive writers do.
+    enum Entry<'a> {
+        Dir(&'a str),
+        File(&'a str, &'a str),
+        Symlink(&'a str, &'a str),
+    }
+
+    fn tar_gz(dir: &TempDir, entries: &[Entry]) -> PathBuf {
+        let path = dir.path().join("archive.tar.gz");
+        let encoder =
+            GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+
+        for entry in entries {
+            let (name, data, kind, link) = match *entry {
+                Entry::Dir(name) => (name, "", EntryType::Directory, ""),
+                Entry::File(name, data) => (name, data, EntryType::Regular, ""),
+                Entry::Symlink(name, link) => (name, "", EntryType::Symlink, link),
+            };
+
+            let mut header = tar::Header::new_gnu();
+            let old = header.as_old_mut();
+            old.name[..name.len()].copy_from_slice(name.as_bytes());
+            old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_entry_type(kind);
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, data.as_bytes()).unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    fn zip(dir: &TempDir, entries: &[Entry]) -> PathBuf {
+        let path = dir.path().join("archive.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+
+        for entry in entries {
+            match *entry {
+                Entry::Dir(name) => writer.add_directory(name, FileOptions::default()),
+                Entry::File(name, data) => {
+                    writer.start_file(name, FileOptions::default()).and_then(|()| {
+                        Ok(io::Write::write_all(&mut writer, data.as_bytes())?)
+                    })
+                }
+                Entry::Symlink(name, link) => {
+                    writer.add_symlink(name, link, FileOptions::default())
+                }
+            }
+            .unwrap();
+        }
+
+        writer.finish().unwrap();
+        path
+    }
+
+    /// Unpacks `entries` as both a `.tar.gz` and a `.zip` archive, returning
+    /// the results and the directory of the archives.
+    fn unpack_both(entries: &[Entry]) -> Vec<(TempDir, anyhow::Result<PathBuf>)> {
+        [Format::TarGz, Format::Zip]
+            .into_iter()
+            .map(|format| {
+                let dir = tempfile::tempdir().unwrap();
+                let archive = match format {
+                    Format::Zip => zip(&dir, entries),
+                    _ => tar_gz(&dir, entries),
+                };
+
+                let res = unpack(&archive, format, &dir.path().join("out"));
+                (dir, res)
+            })
+            .collect()
+    }
+
+    fn assert_rejected(entries: &[Entry]) {
+        for (dir, res) in unpack_both(entries) {
+            let err = res.unwrap_err();
+            assert!(
+                matches!(err.downcast_ref::<Error>(), Some(Error::InvalidPackage(_))),
+                "{err:#}"
+            );
+            assert!(!dir.path().join("evil").exists());
+        }
+    }
+
+    #[test]
+    fn unpacks_into_top_level_folder() {
+        for (_dir, res) in unpack_both(&[
+            Entry::Dir("mypkg/"),
+            Entry::File("mypkg/typst.toml", "[package]"),
+            Entry::File("mypkg/src/lib.typ", "#let x = 1"),
+            Entry::Symlink("mypkg/lib.typ", "src/lib.typ"),
+        ]) {
+            let root = res.unwrap();
+            assert!(root.ends_with("out/mypkg"));
+            assert_eq!(
+                fs::read_to_string(root.join("src/lib.typ")).unwrap(),
+                "#let x = 1"
+            );
+
+            #[cfg(unix)]
+            assert_eq!(fs::read_to_string(root.join("lib.typ")).unwrap(), "#let x = 1");
+        }
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        assert_rejected(&[Entry::File("../evil", "")]);
+        assert_rejected(&[Entry::File("mypkg/../../evil", "")]);
+        assert_rejected(&[Entry::File("/tmp/evil", "")]);
+    }
+
+    #[test]
+    fn rejects_escaping_symlinks() {
+        assert_rejected(&[Entry::Symlink("link", "../evil")]);
+        assert_rejected(&[Entry::Symlink("mypkg/link", "../../evil")]);
+        assert_rejected(&[Entry::Symlink("mypkg/link", "sub/../../../evil")]);
+        assert_rejected(&[Entry::Symlink("link", "/tmp/evil")]);
+    }
+
+    #[test]
+    fn rejects_symlinks_without_a_name() {
+        assert_rejected(&[Entry::Symlink(".", "..")]);
+
+        // Zip entries ending in a slash are directories.
+        let dir = tempfile::tempdir().unwrap();
+        let archive = tar_gz(&dir, &[Entry::Symlink("./", "../evil")]);
+        let err = unpack(&archive, Format::TarGz, &dir.path().join("out")).unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::InvalidPackage(_))));
+    }
+
+    #[test]
+    fn rejects_entries_inside_symlinks() {
+        assert_rejected(&[
+            Entry::Dir("sub/"),
+            Entry::Symlink("link", "sub"),
+            Entry::File("link/evil", ""),
+        ]);
+    }
+}
@@ -0,0 +1,803 @@
+//! Validation of `typst.toml` package manifests.
+//!
+//! Reports every problem in a manifest as a diagnostic pointing at the
+//! offending TOML, rather than stopping at the first one.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Write},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label, Severity},
+    files::SimpleFile,
+    term::{self, termcolor::WriteColor},
+};
+use ignore::gitignore::GitignoreBuilder;
+use semver::Version;
+use serde::Deserialize;
+use toml::{Spanned, Value};
+use url::Url;
+use walkdir::WalkDir;
+
+use crate::{
+    cli::CheckCommand,
+    color_stream,
+    error::Error,
+    package::{self, Package},
+};
+
+/// The categories packages can be listed under on Typst Universe.
+const CATEGORIES: &[&str] = &[
+    "components",
+    "visualization",
+    "model",
+    "layout",
+    "text",
+    "languages",
+    "scripting",
+    "integration",
+    "utility",
+    "fun",
+    "book",
+    "report",
+    "paper",
+    "thesis",
+    "poster",
+    "flyer",
+    "presentation",
+    "cv",
+    "office",
+];
+
+/// The maximum number of categories a package can be listed under.
+const MAX_CATEGORIES: usize = 3;
+
+/// The disciplines packages can be targeted at on Typst Universe.
+const DISCIPLINES: &[&str] = &[
+    "agriculture",
+    "anthropology",
+    "archaeology",
+    "architecture",
+    "biology",
+    "business",
+    "chemistry",
+    "communication",
+    "computer-science",
+    "design",
+    "drawing",
+    "economics",
+    "education",
+    "engineering",
+    "fashion",
+    "film",
+    "geography",
+    "geology",
+    "history",
+    "journalism",
+    "law",
+    "linguistics",
+    "literature",
+    "mathematics",
+    "medicine",
+    "music",
+    "painting",
+    "philosophy",
+    "photography",
+    "physics",
+    "politics",
+    "psychology",
+    "sociology",
+    "theater",
+    "theology",
+    "transportation",
+];
+
+/// The fields of the `[package]` section.
+const PACKAGE_FIELDS: &[&str] = &[
+    "name",
+    "version",
+    "entrypoint",
+    "authors",
+    "license",
+    "description",
+    "repository",
+    "homepage",
+    "keywords",
+    "categories",
+    "disciplines",
+    "compiler",
+    "exclude",
+];
+
+/// The fields of the `[template]` section.
+const TEMPLATE_FIELDS: &[&str] = &["path", "entrypoint", "thumbnail"];
+
+/// A TOML table that keeps track of where its keys and values are.
+type Table = BTreeMap<Spanned<String>, Spanned<Value>>;
+
+/// A manifest as written, with spans, so problems can be pointed at.
+#[derive(Debug, Deserialize)]
+struct RawManifest {
+    package: Option<Spanned<Table>>,
+    template: Option<Spanned<Table>>,
+}
+
+/// Checks the manifests of the package at the current working directory or the
+/// given `path`, or of every package up to two subdirectories deep. Packages
+/// with a usable manifest are also [verified](verify) to be self-contained.
+///
+/// Unlike `install`, manifests are checked even when they are too broken to be
+/// considered a package.
+///
+/// # Errors
+///
+/// Fails if there is no manifest to check, or if any manifest has errors.
+/// Warnings alone do not fail the check.
+pub fn check(command: CheckCommand) -> anyhow::Result<()> {
+    let path = match command.path {
+        Some(path) => path,
+        None => {
+            env::current_dir().context("failed to read the current working directory")?
+        }
+    };
+
+    let manifests: Vec<PathBuf> = match path.join("typst.toml").is_file() {
+        true => vec![path.join("typst.toml")],
+        false => WalkDir::new(&path)
+            .min_depth(1)
+            .max_depth(2)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|e| e.path().join("typst.toml"))
+            .filter(|manifest| manifest.is_file())
+            .collect(),
+    };
+
+    if manifests.is_empty() {
+        bail!(Error::NotFound(format!("no typst.toml found in {}", path.display())));
+    }
+
+    let mut errors = 0;
+    let mut failed = 0;
+    for manifest in &manifests {
+        let source = fs::read_to_string(manifest)
+            .with_context(|| format!("failed to read {}", manifest.display()))?;
+
+        let diagnostics = diagnostics(manifest.parent().unwrap(), &source);
+        let mut count =
+            diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+
+        let file = SimpleFile::new(manifest.display().to_string(), source.as_str());
+        print_diagnostics(&file, &diagnostics)?;
+
+        // Only packages with a usable manifest know which files they consist of.
+        if let Some(package) = package::inspect(&manifest.parent().unwrap()).valid() {
+            let problems = verify(&package)?;
+            count += problems.len();
+            print_problems(&package, &problems)?;
+        }
+
+        errors += count;
+        failed += usize::from(count > 0);
+    }
+
+    if errors > 0 {
+        bail!(Error::InvalidManifest(format!(
+            "found {errors} error(s) in {failed} of {} manifest(s)",
+            manifests.len()
+        )));
+    }
+
+    print_checked(manifests.len()).unwrap();
+
+    Ok(())
+}
+
+/// A problem that keeps a package from being imported.
+#[derive(Debug)]
+pub struct Problem {
+    /// The file the problem is in, relative to the package root.
+    pub file: PathBuf,
+    /// Where in `file` the problem is, if it is about a specific part of it.
+    pub span: Option<Range<usize>>,
+    pub message: String,
+}
+
+/// Verifies that `package` is self-contained, i.e. that it can be imported
+/// once it is vendored.
+///
+/// Checks that the entrypoint is one of the vendored [files](package::files)
+/// and that the string paths of every `import` and `include` in its Typst
+/// sources stay inside the package and point at vendored files. Package imports
+/// such as `@preview/pkg:1.0.0` are not checked.
+///
+/// # Errors
+///
+/// The files of the package could not be listed or read.
+pub fn verify(package: &Package) -> anyhow::Result<Vec<Problem>> {
+    let files = package::files(package)?;
+    let mut problems = Vec::new();
+
+    let entrypoint = &package.manifest.package.entrypoint;
+    if let Some(message) = resolve(package, &files, Path::new(""), entrypoint).err() {
+        let source =
+            fs::read_to_string(package.path.join("typst.toml")).unwrap_or_default();
+        let quoted = format!("\"{}\"", entrypoint.display());
+        let span = source.find(&quoted).map(|start| start..start + quoted.len());
+        let file = PathBuf::from("typst.toml");
+        problems.push(Problem {
+            file,
+            span,
+            message: format!("entrypoint {message}"),
+        });
+    }
+
+    for file in files.iter().filter(|f| f.extension().is_some_and(|ext| ext == "typ")) {
+        let source = fs::read_to_string(package.path.join(file))
+            .with_context(|| format!("failed to read {}", file.display()))?;
+
+        for (keyword, path, span) in imports(&source) {
+            if path.starts_with('@') {
+                continue;
+            }
+
+            let base = file.parent().unwrap_or(Path::new(""));
+            if let Err(message) = resolve(package, &files, base, Path::new(&path)) {
+                let message = format!("{keyword} {message}");
+                problems.push(Problem { file: file.clone(), span: Some(span), message });
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Resolves `path` relative to `base` in `package`, where paths starting with
+/// `/` are relative to the package root, like the compiler does.
+///
+/// Fails with a description of the problem if the resolved path is not one of
+/// the vendored `files`.
+fn resolve(
+    package: &Package,
+    files: &[PathBuf],
+    base: &Path,
+    path: &Path,
+) -> Result<PathBuf, String> {
+    let joined = match path.strip_prefix("/") {
+        Ok(path) => path.to_path_buf(),
+        Err(_) => base.join(path),
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if resolved.pop() => {}
+            _ => return Err(format!("`{}` is outside of the package", path.display())),
+        }
+    }
+
+    if files.contains(&resolved) {
+        Ok(resolved)
+    } else if package.path.join(&resolved).is_file() {
+        Err(format!("`{}` is excluded from the package", path.display()))
+    } else {
+        Err(format!("`{}` does not exist", path.display()))
+    }
+}
+
+/// Finds the string paths of the `import` and `include` statements in the Typst
+/// `source`, with their keyword and the span of each string.
+///
+/// This is a heuristic rather than a parser: comments are skipped and a keyword
+/// only counts when it is embedded with `#` or follows a `{` or `;` in code.
+fn imports(source: &str) -> Vec<(&'static str, String, Range<usize>)> {
+    let code = strip_comments(source);
+    let mut imports = Vec::new();
+
+    for keyword in ["import", "include"] {
+        for (start, _) in code.match_indices(keyword) {
+            let before = code[..start].trim_end_matches(char::is_whitespace);
+            if !code[..start].ends_with('#') && !before.ends_with(['{', ';']) {
+                continue;
+            }
+
+            let after = start + keyword.len();
+            let rest = code[after..].trim_start_matches([' ', '\t']);
+            let open = code.len() - rest.len();
+            if open == after || !rest.starts_with('"') {
+                continue;
+            }
+
+            if let Some(len) = rest[1..]
+                .find(['"', '\n'])
+                .filter(|&n| rest[1 + n..].starts_with('"'))
+            {
+                imports.push((
+                    keyword,
+                    rest[1..1 + len].to_owned(),
+                    open..open + len + 2,
+                ));
+            }
+        }
+    }
+
+    imports.sort_by_key(|(_, _, span)| span.start);
+    imports
+}
+
+/// Blanks out the line and (nested) block comments in Typst `source`, keeping
+/// every other byte at the same offset.
+///
+/// Strings are skipped, so `"assets/*"` does not start a comment. Like import
+/// paths, they are taken to end at the end of the line.
+fn strip_comments(source: &str) -> String {
+    let mut bytes = source.as_bytes().to_vec();
+    let mut i = 0;
+
+    while i + 1 < bytes.len() {
+        match &bytes[i..i + 2] {
+            [b'"', _] => {
+                i += 1;
+                while i < bytes.len() && !matches!(bytes[i], b'"' | b'\n') {
+                    i += match bytes[i] {
+                        b'\\' => 2,
+                        _ => 1,
+                    };
+                }
+                i += 1;
+            }
+            b"//" => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    bytes[i] = b' ';
+                    i += 1;
+                }
+            }
+            b"/*" => {
+                let mut depth = 0;
+                while i < bytes.len() {
+                    if bytes[i..].starts_with(b"/*") {
+                        depth += 1;
+                        bytes[i..i + 2].fill(b' ');
+                        i += 2;
+                    } else if bytes[i..].starts_with(b"*/") {
+                        depth -= 1;
+                        bytes[i..i + 2].fill(b' ');
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        if bytes[i] != b'\n' {
+                            bytes[i] = b' ';
+                        }
+                        i += 1;
+                    }
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    // Only whole comments were blanked, so every multi-byte character is either
+    // untouched or entirely replaced.
+    String::from_utf8(bytes).unwrap()
+}
+
+/// Print the problems found while verifying `package`.
+pub fn print_problems(package: &Package, problems: &[Problem]) -> anyhow::Result<()> {
+    for problem in problems {
+        let path = package.path.join(&problem.file);
+        let source = fs::read_to_string(&path).unwrap_or_default();
+
+        let mut diagnostic = Diagnostic::error().with_message(&problem.message);
+        if let Some(span) = problem.span.clone().filter(|span| span.end <= source.len()) {
+            diagnostic = diagnostic.with_labels(vec![Label::primary((), span)]);
+        }
+
+        let file = SimpleFile::new(path.display().to_string(), source.as_str());
+        print_diagnostics(&file, &[diagnostic])?;
+    }
+
+    Ok(())
+}
+
+/// Checks the manifest `source` of the package at `root`.
+pub fn diagnostics(root: &Path, source: &str) -> Vec<Diagnostic<()>> {
+    let mut checker = Checker { root, source, diagnostics: Vec::new() };
+    checker.manifest();
+    checker.diagnostics
+}
+
+/// Collects the problems of a single manifest.
+struct Checker<'a> {
+    root: &'a Path,
+    source: &'a str,
+    diagnostics: Vec<Diagnostic<()>>,
+}
+
+impl Checker<'_> {
+    /// Checks the whole manifest.
+    fn manifest(&mut self) {
+        let manifest: RawManifest = match toml::from_str(self.source) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                let span = err.span().unwrap_or(0..0);
+                self.error(span, err.message().to_owned());
+                return;
+            }
+        };
+
+        match manifest.package {
+            Some(package) => self.package(package),
+            None => self.error(0..0, "missing `[package]` section".into()),
+        }
+
+        if let Some(template) = manifest.template {
+            self.template(template);
+        }
+    }
+
+    /// Checks the `[package]` section.
+    fn package(&mut self, package: Spanned<Table>) {
+        let span = package.span();
+        let table = package.into_inner();
+
+        self.unknown_fields(&table, PACKAGE_FIELDS, "package");
+
+        match self.string(&table, "name") {
+            Some((name, span)) if !is_kebab_case(&name) => {
+                self.error(span, format!("package name `{name}` is not in kebab-case"))
+            }
+            Some(_) => {}
+            None => self.missing(&table, span.clone(), "name"),
+        }
+
+        match self
+            .string(&table, "version")
+            .map(|(v, span)| (Version::parse(&v), v, span))
+        {
+            Some((Ok(version), _, span))
+                if !version.pre.is_empty() || !version.build.is_empty() =>
+            {
+                let message =
+                    format!("version `{version}` is not a major.minor.patch triple");
+                self.error(span, message);
+            }
+            Some((Ok(_), _, _)) => {}
+            Some((Err(err), version, span)) => {
+                self.error(span, format!("invalid version `{version}`: {err}"));
+            }
+            None => self.missing(&table, span.clone(), "version"),
+        }
+
+        match self.string(&table, "entrypoint") {
+            Some((entrypoint, span)) => {
+                self.file(span, &entrypoint, self.root, "entrypoint")
+            }
+            None => self.missing(&table, span.clone(), "entrypoint"),
+        }
+
+        for (author, span) in self.strings(&table, "authors") {
+            if author.trim().is_empty() {
+                self.error(span, "author must not be empty".into());
+            }
+        }
+
+        if let Some((license, span)) = self.string(&table, "license") {
+            if let Err(err) = spdx::Expression::parse(&license) {
+                self.error(
+                    span,
+                    format!(
+                        "invalid SPDX license expression `{license}`: {}",
+                        err.reason
+                    ),
+                );
+            }
+        }
+
+        if let Some((description, span)) = self.string(&table, "description") {
+            if description.trim().is_empty() {
+                self.warning(span, "description is empty".into());
+            }
+        }
+
+        for field in ["repository", "homepage"] {
+            if let Some((url, span)) = self.string(&table, field) {
+                if let Err(err) = Url::parse(&url) {
+                    self.error(span, format!("invalid {field} url `{url}`: {err}"));
+                }
+            }
+        }
+
+        for (keyword, span) in self.strings(&table, "keywords") {
+            if keyword.trim().is_empty() {
+                self.error(span, "keyword must not be empty".into());
+            }
+        }
+
+        let categories = self.strings(&table, "categories");
+        if categories.len() > MAX_CATEGORIES {
+            let span = table.get("categories").unwrap().span();
+            self.error(span, format!("at most {MAX_CATEGORIES} categories are allowed"));
+        }
+
+        for (category, span) in categories {
+            if !CATEGORIES.contains(&category.as_str()) {
+                self.error(span, format!("unknown category `{category}`"));
+            }
+        }
+
+        for (discipline, span) in self.strings(&table, "disciplines") {
+            if !DISCIPLINES.contains(&discipline.as_str()) {
+                self.error(span, format!("unknown discipline `{discipline}`"));
+            }
+        }
+
+        if let Some((compiler, span)) = self.string(&table, "compiler") {
+            if !is_version_bound(&compiler) {
+                self.error(span, format!("invalid compiler version `{compiler}`"));
+            }
+        }
+
+        for (glob, span) in self.strings(&table, "exclude") {
+            if let Err(err) = GitignoreBuilder::new(self.root).add_line(None, &glob) {
+                self.error(span, format!("invalid exclude glob `{glob}`: {err}"));
+            }
+        }
+    }
+
+    /// Checks the `[template]` section.
+    fn template(&mut self, template: Spanned<Table>) {
+        let span = template.span();
+        let table = template.into_inner();
+
+        self.unknown_fields(&table, TEMPLATE_FIELDS, "template");
+
+        let path = match self.string(&table, "path") {
+            Some((path, span)) => {
+                self.dir(span, &path);
+                Some(self.root.join(path))
+            }
+            None => {
+                self.missing(&table, span.clone(), "path");
+                None
+            }
+        };
+
+        match self.string(&table, "entrypoint") {
+            Some((entrypoint, span)) => {
+                if let Some(path) = path.filter(|path| path.is_dir()) {
+                    self.file(span, &entrypoint, &path, "template entrypoint");
+                }
+            }
+            None => self.missing(&table, span, "entrypoint"),
+        }
+
+        if let Some((thumbnail, span)) = self.string(&table, "thumbnail") {
+            self.file(span, &thumbnail, self.root, "thumbnail");
+        }
+    }
+
+    /// Reports every field of `table` that is not one of the `known` fields.
+    fn unknown_fields(&mut self, table: &Table, known: &[&str], section: &str) {
+        for key in table.keys() {
+            if !known.contains(&key.get_ref().as_str()) {
+                self.warning(
+                    key.span(),
+                    format!("unknown field `{}` in `[{section}]`", key.get_ref()),
+                );
+            }
+        }
+    }
+
+    /// Gets the string `field` of `table`, reporting it if it is not a string.
+    fn string(&mut self, table: &Table, field: &str) -> Option<(String, Range<usize>)> {
+        let value = table.get(field)?;
+
+        match value.get_ref() {
+            Value::String(s) => Some((s.clone(), value.span())),
+            other => {
+                let found = other.type_str();
+                self.error(
+                    value.span(),
+                    format!("`{field}` must be a string, found {found}"),
+                );
+                None
+            }
+        }
+    }
+
+    /// Gets the array of strings `field` of `table`, reporting it if it is not
+    /// an array of strings. Each string is paired with its own span.
+    fn strings(&mut self, table: &Table, field: &str) -> Vec<(String, Range<usize>)> {
+        let Some(value) = table.get(field) else {
+            return Vec::new();
+        };
+
+        let Value::Array(array) = value.get_ref() else {
+            let found = value.get_ref().type_str();
+            self.error(
+                value.span(),
+                format!("`{field}` must be an array, found {found}"),
+            );
+            return Vec::new();
+        };
+
+        let mut strings = Vec::new();
+        for item in array {
+            match item {
+                Value::String(s) => {
+                    strings.push((s.clone(), self.find(value.span(), s)));
+                }
+                other => {
+                    let found = other.type_str();
+                    self.error(
+                        value.span(),
+                        format!("`{field}` must only contain strings, found {found}"),
+                    );
+                }
+            }
+        }
+
+        strings
+    }
+
+    /// Reports the required `field` of the section at `span` if it is missing.
+    fn missing(&mut self, table: &Table, span: Range<usize>, field: &str) {
+        if table.contains_key(field) {
+            return;
+        }
+
+        // Point at the section header rather than the whole section.
+        let end = self.source[span.clone()]
+            .find('\n')
+            .map_or(span.end, |n| span.start + n);
+        self.error(span.start..end, format!("missing required field `{field}`"));
+    }
+
+    /// Reports `path` if it does not point to a file inside the package, when
+    /// resolved relative to `base`.
+    fn file(&mut self, span: Range<usize>, path: &str, base: &Path, what: &str) {
+        if escapes(Path::new(path)) {
+            self.error(span, format!("{what} `{path}` is outside of the package"));
+        } else if !base.join(path).is_file() {
+            self.error(span, format!("{what} `{path}` does not exist"));
+        }
+    }
+
+    /// Reports `path` if it does not point to a directory inside the package.
+    fn dir(&mut self, span: Range<usize>, path: &str) {
+        if escapes(Path::new(path)) {
+            self.error(span, format!("template path `{path}` is outside of the package"));
+        } else if !self.root.join(path).is_dir() {
+            self.error(span, format!("template path `{path}` does not exist"));
+        }
+    }
+
+    /// Narrows `span` down to the quoted `needle` inside of it, to point at a
+    /// single item of an array.
+    fn find(&self, span: Range<usize>, needle: &str) -> Range<usize> {
+        ["\"", "'"]
+            .iter()
+            .find_map(|quote| {
+                let quoted = format!("{quote}{needle}{quote}");
+                let start = span.start + self.source[span.clone()].find(&quoted)?;
+                Some(start..start + quoted.len())
+            })
+            .unwrap_or(span)
+    }
+
+    /// Reports an error at `span`.
+    fn error(&mut self, span: Range<usize>, message: String) {
+        self.diagnostics.push(
+            Diagnostic::error()
+                .with_message(message)
+                .with_labels(vec![Label::primary((), span)]),
+        );
+    }
+
+    /// Reports a warning at `span`.
+    fn warning(&mut self, span: Range<usize>, message: String) {
+        self.diagnostics.push(
+            Diagnostic::warning()
+                .with_message(message)
+                .with_labels(vec![Label::primary((), span)]),
+        );
+    }
+}
+
+/// Whether `name` is in kebab-case: lowercase ASCII letters and digits, with
+/// single hyphens in between words, starting with a letter.
+pub fn is_kebab_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.split('-').all(|word| {
+            !word.is_empty()
+                && word.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+}
+
+/// Whether `bound` is a version bound such as `1`, `1.2` or `1.2.3`.
+fn is_version_bound(bound: &str) -> bool {
+    let parts: Vec<&str> = bound.split('.').collect();
+    parts.len() <= 3 && parts.iter().all(|part| part.parse::<u64>().is_ok())
+}
+
+/// Whether the relative `path` escapes the directory it is relative to.
+fn escapes(path: &Path) -> bool {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return true,
+        }
+    }
+
+    false
+}
+
+/// Print the diagnostics of a single file.
+fn print_diagnostics(
+    file: &SimpleFile<String, &str>,
+    diagnostics: &[Diagnostic<()>],
+) -> anyhow::Result<()> {
+    let mut w = color_stream();
+    let config = term::Config::default();
+
+    for diagnostic in diagnostics {
+        term::emit(&mut w, &config, file, diagnostic)?;
+    }
+
+    Ok(())
+}
+
+/// Print that the manifests were checked without errors.
+fn print_checked(count: usize) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_help)?;
+    write!(w, "checked")?;
+
+    w.reset()?;
+    writeln!(w, " {count} manifest(s), no errors found")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_comments_skips_strings() {
+        let source = "#let assets = \"assets/*\"\n\
+                      #let url = \"https://typst.app\" // a comment\n\
+                      #let quote = \"a \\\" /* b\"\n\
+                      #import \"../escape.typ\": *\n";
+        let code = strip_comments(source);
+
+        assert_eq!(code.len(), source.len());
+        assert!(code.contains("\"assets/*\""));
+        assert!(code.contains("\"https://typst.app\" "));
+        assert!(!code.contains("a comment"));
+        assert!(code.contains("\"a \\\" /* b\""));
+
+        let imports = imports(source);
+        assert_eq!(imports.len(), 1);
+        assert_eq!(imports[0].1, "../escape.typ");
+    }
+
+    #[test]
+    fn strip_comments_blanks_comments() {
+        let source = "a // b\nc /* d /* e */ f */ g\n/* \"h */ i";
+        assert_eq!(strip_comments(source), "a     \nc                   g\n         i");
+    }
+}
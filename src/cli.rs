@@ -1,11 +1,20 @@
 //! The Command-Line Interface (CLI).
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use clap::{Parser, Subcommand};
+use clap::{
+    error::ErrorKind, ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum,
+};
 use semver::Version;
 use url::Url;
 
+use crate::{
+    filter::Filter,
+    git::Reference,
+    package::{self, DEFAULT_NAMESPACE},
+    time,
+};
+
 /// The typven CLI.
 #[derive(Parser, Debug)]
 #[command(name = "typven", version = env!("CARGO_PKG_VERSION"))]
@@ -18,30 +27,196 @@ pub struct Cli {
 #[derive(Debug, Subcommand)]
 pub enum Command {
     /// Install package(s) from the current working directory or a given `path`.
-    Install(InstallCommand),
+    Install(Box<InstallCommand>),
 
-    /// List locally installed packages in table format.
-    Ls,
+    /// List locally installed packages as a table, JSON, TOML or plain text.
+    Ls(LsCommand),
 
     /// Self update the typven CLI
     #[cfg_attr(not(feature = "self-update"), doc = " (disabled)")]
     Update(UpdateCommand),
 
-    /// Clean all installed local packages, or clean a target package either by
-    /// name or name and version.
+    /// Clean all installed local packages, or the packages that match specs
+    /// such as `mypkg`, `mypkg:0.2.5`, `mypkg@<1.0` or `report-*`.
     Clean(CleanCommand),
+
+    /// Check the `typst.toml` manifest(s) in the current working directory or a
+    /// given `path` for problems.
+    Check(CheckCommand),
+
+    /// Make the local package directory match the `typven.toml` project manifest
+    /// in the current working directory or a given `path`.
+    Sync(SyncCommand),
+
+    /// Verify that installed packages still contain exactly the files they were
+    /// installed with.
+    Verify(VerifyCommand),
+
+    /// Show the manifest, size and install metadata of an installed package.
+    Info(InfoCommand),
+
+    /// Restore cleaned package versions from the trash.
+    Restore(RestoreCommand),
+
+    /// List or empty the trash that cleaned package versions are moved to.
+    Trash(TrashCommand),
+
+    /// Find and remove broken entries in the local package directory.
+    Prune(PruneCommand),
+
+    /// Report where packages are installed, whether Typst finds them there and
+    /// how to fix problems.
+    Doctor(DoctorCommand),
 }
 
 /// Install package(s) from the current working directory or a given `path`.
-#[derive(Debug, Parser)]
+#[derive(Debug, Default, Parser)]
+#[clap(group(ArgGroup::new("reference").multiple(false)))]
 pub struct InstallCommand {
     /// Install package(s) from `path` instead of the current working directory.
+    /// `path` is either a directory or a `.tar.gz`, `.tar.xz` or `.zip` archive.
     pub path: Option<PathBuf>,
 
-    /// Fetches the repository from `url` and store it in the `temp` folder for
-    /// installation.
-    #[clap(long = "git", value_name = "URL", exclusive = true)]
+    /// Clones the repository at `url` into a temporary directory and installs
+    /// package(s) from it.
+    #[clap(long, value_name = "URL", conflicts_with = "path")]
+    pub git: Option<Url>,
+
+    /// Downloads the `.tar.gz`, `.tar.xz` or `.zip` archive at `url` and
+    /// installs package(s) from it.
+    #[clap(long, value_name = "URL", conflicts_with_all = ["path", "git"])]
     pub url: Option<Url>,
+
+    /// The SHA-256 digest the downloaded archive has to match. Pinned archives
+    /// are cached, so installing them again works offline.
+    #[clap(long, value_name = "DIGEST", requires = "url", value_parser = parse_sha256)]
+    pub sha256: Option<String>,
+
+    /// Checks out the tip of `branch` instead of the default branch.
+    #[clap(long, value_name = "BRANCH", requires = "git", group = "reference")]
+    pub branch: Option<String>,
+
+    /// Checks out `tag` instead of the default branch.
+    #[clap(long, value_name = "TAG", requires = "git", group = "reference")]
+    pub tag: Option<String>,
+
+    /// Checks out the commit `rev` instead of the default branch.
+    #[clap(long, value_name = "REV", requires = "git", group = "reference")]
+    pub rev: Option<String>,
+
+    /// Installs package(s) from `subdir` inside the repository instead of its
+    /// root.
+    #[clap(long, value_name = "SUBDIR", requires = "git")]
+    pub subdir: Option<PathBuf>,
+
+    /// Keeps a mirror of the repository in the clone cache and clones from it,
+    /// so repeat installs only fetch new commits.
+    #[clap(long, default_value_t = false, requires = "git")]
+    pub git_cache: bool,
+
+    /// The namespace to install into, imported as `@{namespace}/{name}:{version}`.
+    #[clap(
+        long,
+        short,
+        value_name = "NAMESPACE",
+        default_value = DEFAULT_NAMESPACE,
+        value_parser = parse_namespace
+    )]
+    pub namespace: String,
+
+    /// Symlinks the package(s) to their source instead of copying them, so
+    /// edits are picked up without reinstalling.
+    /// Excluded files are still visible through the link.
+    #[clap(long, default_value_t = false, conflicts_with_all = ["git", "url"])]
+    pub link: bool,
+
+    /// Replaces package versions that are already installed.
+    #[clap(long, default_value_t = false)]
+    pub force: bool,
+
+    /// Replaces package versions that are already installed, but only when
+    /// their content changed.
+    #[clap(long, default_value_t = false, conflicts_with = "force")]
+    pub update_if_changed: bool,
+
+    /// Installs either every package or none of them, rolling back the packages
+    /// installed so far when one of them fails.
+    #[clap(long, default_value_t = false)]
+    pub atomic: bool,
+
+    /// Lists the files that would be copied for each package instead of
+    /// installing them.
+    #[clap(long, default_value_t = false, conflicts_with = "link")]
+    pub list_files: bool,
+
+    /// Installs package(s) even when their entrypoint is missing or their
+    /// imports point outside of the package.
+    #[clap(long, default_value_t = false)]
+    pub no_verify: bool,
+}
+
+impl InstallCommand {
+    /// The git reference to check out, or `None` for the default branch.
+    pub fn reference(&self) -> Option<Reference> {
+        match (&self.branch, &self.tag, &self.rev) {
+            (Some(branch), _, _) => Some(Reference::Branch(branch.clone())),
+            (_, Some(tag), _) => Some(Reference::Tag(tag.clone())),
+            (_, _, Some(rev)) => Some(Reference::Rev(rev.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// List locally installed packages as a table, JSON, TOML or plain text.
+#[derive(Debug, Parser)]
+pub struct LsCommand {
+    /// Only lists the packages that match `filter`, written as
+    /// `name[@requirement]` or `name:version`. `name` is a glob such as
+    /// `report-*` or a regex between slashes such as `/^report-\d+$/`,
+    /// `requirement` a semver requirement such as `^1.2`.
+    #[clap(value_name = "FILTER", value_parser = Filter::parse)]
+    pub filter: Option<Filter>,
+
+    /// Only lists the newest version of each package.
+    #[clap(long, default_value_t = false)]
+    pub latest: bool,
+
+    /// What to sort the packages by.
+    #[clap(long, value_enum, default_value_t = SortKey::Name)]
+    pub sort: SortKey,
+
+    /// The format to list the packages in.
+    #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub namespace: NamespaceArgs,
+}
+
+/// What `ls` sorts packages by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// By namespace and name.
+    Name,
+    /// By the number of installed versions, most first.
+    Versions,
+    /// By the size of all installed versions together, largest first.
+    Size,
+    /// By when the package was last installed, most recent first.
+    Installed,
+}
+
+/// The formats to print output in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A table for humans.
+    Table,
+    /// JSON for tooling.
+    Json,
+    /// TOML for tooling.
+    Toml,
+    /// Plain text, one entry per line.
+    Plain,
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -59,13 +234,303 @@ pub struct UpdateCommand {
     pub revert: bool,
 }
 
-/// Clean all installed local packages, or clean a target package either by
-/// name or name and version.
+/// Clean all installed local packages, or the packages that match specs
+/// such as `mypkg`, `mypkg:0.2.5`, `mypkg@<1.0` or `report-*`.
 #[derive(Debug, Parser)]
 pub struct CleanCommand {
-    /// Package name to clean, will remove all versions for this package.
-    pub name: Option<String>,
+    /// The packages to clean, written as `name`, `name:version` or
+    /// `name@requirement`. `name` is a glob such as `report-*` or a regex
+    /// between slashes, `requirement` a semver requirement such as `<1.0`.
+    /// A version following a name, as in `mypkg 0.2.5`, is the same as
+    /// `mypkg:0.2.5`. Cleans every package if there are none.
+    #[clap(value_name = "SPEC", value_parser = parse_spec)]
+    pub specs: Vec<Spec>,
+
+    /// List what would be removed and how much space that frees, without
+    /// removing anything.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Do not ask for confirmation before removing packages. Required when
+    /// stdin is not a terminal.
+    #[clap(long, short)]
+    pub yes: bool,
+
+    /// Keep the newest N versions of every package.
+    #[clap(long, value_name = "N")]
+    pub keep_latest: Option<usize>,
+
+    /// Only remove versions installed longer ago than this, such as `30d`.
+    #[clap(long, value_name = "AGE", value_parser = time::parse_duration)]
+    pub older_than: Option<Duration>,
 
-    /// Cleans the target version of the given package.
+    /// Delete the package versions for good instead of moving them to the
+    /// trash.
+    #[clap(long)]
+    pub no_trash: bool,
+
+    #[command(flatten)]
+    pub namespace: NamespaceArgs,
+}
+
+/// Selects which namespace(s) of the local package directory to operate on.
+#[derive(Debug, Clone, Args)]
+pub struct NamespaceArgs {
+    /// The namespace to operate on.
+    #[clap(
+        long,
+        short,
+        value_name = "NAMESPACE",
+        default_value = DEFAULT_NAMESPACE,
+        value_parser = parse_namespace
+    )]
+    pub namespace: String,
+
+    /// Operate on every namespace in the local package directory.
+    #[clap(long, conflicts_with = "namespace")]
+    pub all_namespaces: bool,
+}
+
+/// Parses a namespace, which has to be a valid Typst identifier so it can be
+/// used in an import path such as `@{namespace}/mypkg:1.0.0`.
+pub fn parse_namespace(namespace: &str) -> Result<String, String> {
+    if !package::is_ident(namespace) {
+        return Err(format!("`{namespace}` is not a valid namespace"));
+    }
+
+    Ok(namespace.to_owned())
+}
+
+/// Parses a SHA-256 digest in hex, normalized to lowercase.
+pub fn parse_sha256(digest: &str) -> Result<String, String> {
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("`{digest}` is not a SHA-256 digest in hex"));
+    }
+
+    Ok(digest.to_ascii_lowercase())
+}
+
+/// Check the `typst.toml` manifest(s) in the current working directory or a
+/// given `path` for problems.
+#[derive(Debug, Parser)]
+pub struct CheckCommand {
+    /// Check manifest(s) in `path` instead of the current working directory.
+    pub path: Option<PathBuf>,
+}
+
+/// Make the local package directory match the `typven.toml` project manifest in
+/// the current working directory or a given `path`.
+#[derive(Debug, Parser)]
+pub struct SyncCommand {
+    /// Use the project manifest in `path` instead of the current working
+    /// directory. `path` is either a directory or the manifest itself.
+    pub path: Option<PathBuf>,
+
+    /// Moves installed package versions that the project manifest does not
+    /// list to the trash, in the namespaces its packages are installed into.
+    #[clap(long, default_value_t = false)]
+    pub prune: bool,
+
+    /// Refuses to install anything unless every package resolves to exactly the
+    /// source, version and content recorded in `typven.lock`.
+    #[clap(long, default_value_t = false)]
+    pub locked: bool,
+
+    /// Installs package(s) even when their entrypoint is missing or their
+    /// imports point outside of the package.
+    #[clap(long, default_value_t = false)]
+    pub no_verify: bool,
+}
+
+/// Verify that installed packages still contain exactly the files they were
+/// installed with.
+#[derive(Debug, Parser)]
+pub struct VerifyCommand {
+    /// The package to verify as `name` or `name:version`, every package if left
+    /// out.
+    #[clap(value_name = "NAME[:VERSION]", value_parser = parse_package)]
+    pub package: Option<PackageArg>,
+
+    /// Reinstalls the packages that do not match from the source they were
+    /// installed from.
+    #[clap(long, default_value_t = false)]
+    pub restore: bool,
+
+    #[command(flatten)]
+    pub namespace: NamespaceArgs,
+}
+
+/// Show the manifest, size and install metadata of an installed package.
+#[derive(Debug, Parser)]
+pub struct InfoCommand {
+    /// The package to show as `name` or `name:version`, every installed version
+    /// if the version is left out.
+    #[clap(value_name = "NAME[:VERSION]", value_parser = parse_package)]
+    pub package: PackageArg,
+
+    #[command(flatten)]
+    pub namespace: NamespaceArgs,
+}
+
+/// Restore cleaned package versions from the trash.
+#[derive(Debug, Parser)]
+pub struct RestoreCommand {
+    /// The package to restore as `name` or `name:version`, every trashed
+    /// version if the version is left out. Restores everything in the trash of
+    /// the namespace, or of every namespace with `--all-namespaces`, if left
+    /// out.
+    #[clap(value_name = "NAME[:VERSION]", value_parser = parse_package)]
+    pub package: Option<PackageArg>,
+
+    #[command(flatten)]
+    pub namespace: NamespaceArgs,
+}
+
+/// List or empty the trash that cleaned package versions are moved to.
+#[derive(Debug, Parser)]
+pub struct TrashCommand {
+    #[command(subcommand)]
+    pub action: TrashAction,
+}
+
+/// What to do with the trash.
+#[derive(Debug, Subcommand)]
+pub enum TrashAction {
+    /// List the package versions in the trash, the most recently trashed first.
+    List,
+
+    /// Delete the package versions in the trash for good.
+    Empty(EmptyTrashCommand),
+}
+
+/// Delete the package versions in the trash for good.
+#[derive(Debug, Parser)]
+pub struct EmptyTrashCommand {
+    /// Only delete versions trashed longer ago than this, such as `7d`.
+    #[clap(long, value_name = "AGE", value_parser = time::parse_duration)]
+    pub older_than: Option<Duration>,
+}
+
+/// Find and remove broken entries in the local package directory.
+#[derive(Debug, Parser)]
+pub struct PruneCommand {
+    /// List the broken entries without removing anything.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Do not ask for confirmation before removing broken entries. Required
+    /// when stdin is not a terminal.
+    #[clap(long, short)]
+    pub yes: bool,
+
+    #[command(flatten)]
+    pub namespace: NamespaceArgs,
+}
+
+/// Report where packages are installed, whether Typst finds them there and how
+/// to fix problems.
+#[derive(Debug, Parser)]
+pub struct DoctorCommand {
+    /// The format to print the report in.
+    #[clap(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+}
+
+/// The formats to print a report in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Text for humans.
+    Text,
+    /// JSON for tooling.
+    Json,
+}
+
+/// A package `name`, optionally with a `version`.
+#[derive(Debug, Clone)]
+pub struct PackageArg {
+    pub name: String,
     pub version: Option<Version>,
 }
+
+/// A spec of the packages to clean.
+#[derive(Debug, Clone)]
+pub enum Spec {
+    Filter(Filter),
+    /// The version of the package named by the spec before it, as in
+    /// `clean mypkg 0.2.5`.
+    Version(Version),
+}
+
+/// Parses a [`Spec`] of the packages to clean.
+fn parse_spec(spec: &str) -> Result<Spec, String> {
+    match spec.parse::<Version>() {
+        Ok(version) => Ok(Spec::Version(version)),
+        Err(_) => Filter::parse(spec).map(Spec::Filter),
+    }
+}
+
+impl CleanCommand {
+    /// The filters of the specs, where a version following a spec without
+    /// one selects that single version: `mypkg 0.2.5` is `mypkg:0.2.5`.
+    ///
+    /// # Errors
+    ///
+    /// When a version does not follow a spec without a version.
+    pub fn filters(&self) -> Result<Vec<Filter>, clap::Error> {
+        let mut filters: Vec<Filter> = Vec::new();
+
+        for spec in &self.specs {
+            match spec {
+                Spec::Filter(filter) => filters.push(filter.clone()),
+                Spec::Version(version) => {
+                    match filters.pop().and_then(|filter| filter.exact(version)) {
+                        Some(filter) => filters.push(filter),
+                        None => {
+                            return Err(usage_error(
+                                "clean",
+                                ErrorKind::ValueValidation,
+                                format!(
+                                    "the version `{version}` has to follow a package \
+                                     name without a version, such as `mypkg {version}`"
+                                ),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(filters)
+    }
+}
+
+/// A usage error of the `subcommand`, reported like the argument parser reports
+/// invalid arguments.
+pub fn usage_error(
+    subcommand: &str,
+    kind: ErrorKind,
+    message: impl std::fmt::Display,
+) -> clap::Error {
+    let mut cli = Cli::command();
+    cli.build();
+    cli.find_subcommand_mut(subcommand).unwrap().error(kind, message)
+}
+
+/// Parses a package as `name` or `name:version`.
+fn parse_package(package: &str) -> Result<PackageArg, String> {
+    let (name, version) = match package.split_once(':') {
+        Some((name, version)) => {
+            let version = version
+                .parse()
+                .map_err(|err| format!("`{version}` is not a valid version: {err}"))?;
+            (name, Some(version))
+        }
+        None => (package, None),
+    };
+
+    if !package::is_ident(name) {
+        return Err(format!("`{name}` is not a valid package name"));
+    }
+
+    Ok(PackageArg { name: name.to_owned(), version })
+}
@@ -0,0 +1,376 @@
+//! Diagnosing whether typven installs packages where Typst looks for them.
+
+use std::{
+    collections::BTreeSet,
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+
+use crate::{
+    cli::{DoctorCommand, ReportFormat},
+    error::Error,
+    git, package, prune,
+};
+
+/// The environment variables that change where packages are stored or looked
+/// up.
+const VARIABLES: &[&str] = &[
+    "XDG_DATA_HOME",
+    "XDG_CACHE_HOME",
+    "TYPST_PACKAGE_PATH",
+    "TYPST_PACKAGE_CACHE_PATH",
+];
+
+/// What `doctor` found out about the environment.
+#[derive(Debug, Serialize)]
+struct Report {
+    typven: &'static str,
+    data_dir: Option<PathBuf>,
+    cache_dir: Option<PathBuf>,
+    /// Where typven installs packages.
+    package_dir: Option<PathBuf>,
+    /// Where Typst caches downloaded packages, `TYPST_PACKAGE_CACHE_PATH` if it
+    /// is set.
+    package_cache_dir: Option<PathBuf>,
+    /// Whether packages can be installed into the package directory.
+    writable: bool,
+    /// The environment variables of [`VARIABLES`] that are set.
+    environment: Vec<Variable>,
+    /// What repositories are cloned with, if anything.
+    git: Option<String>,
+    namespaces: Vec<Namespace>,
+    findings: Vec<Finding>,
+}
+
+/// An environment variable that is set.
+#[derive(Debug, Serialize)]
+struct Variable {
+    name: &'static str,
+    value: String,
+}
+
+/// A namespace in the package directory.
+#[derive(Debug, Serialize)]
+struct Namespace {
+    name: String,
+    packages: usize,
+    versions: usize,
+}
+
+/// A problem with the environment, and how to fix it.
+#[derive(Debug, Serialize)]
+struct Finding {
+    severity: Severity,
+    message: String,
+    fix: String,
+}
+
+/// How bad a [`Finding`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Severity {
+    /// `@local` imports, or installing, do not work.
+    Error,
+    /// Something works differently than expected, or is broken in part.
+    Warning,
+}
+
+/// Reports where typven installs packages and whether Typst finds them there.
+///
+/// Shows the data and cache directories, the environment variables that move
+/// them, whether the package directory can be written to, what git
+/// repositories are cloned with and the namespaces present. Every problem is
+/// reported with a fix, including the broken entries [`prune`] finds.
+///
+/// # Errors
+///
+/// When a problem is found that breaks installing or importing packages, as a
+/// conflict between the environment and where typven installs packages.
+pub fn doctor(command: DoctorCommand) -> anyhow::Result<()> {
+    let report = report()?;
+
+    match command.format {
+        ReportFormat::Text => print_text(&report),
+        ReportFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report)
+                .context("failed to serialize report")?
+        ),
+    }
+
+    let errors = report
+        .findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        bail!(Error::Conflict(format!(
+            "found {errors} problem(s) that break installing or importing packages"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Looks into the environment.
+fn report() -> anyhow::Result<Report> {
+    let data_dir = dirs::data_dir();
+    let cache_dir = dirs::cache_dir();
+    let package_dir = data_dir.as_ref().map(|dir| dir.join("typst/packages"));
+    // Typst only moves its cache, local packages are always read from the data
+    // directory unless `TYPST_PACKAGE_PATH` says otherwise.
+    let package_cache_dir = match env::var_os("TYPST_PACKAGE_CACHE_PATH") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => cache_dir.as_ref().map(|dir| dir.join("typst/packages")),
+    };
+
+    let environment: Vec<Variable> = VARIABLES
+        .iter()
+        .filter_map(|name| {
+            let value = env::var_os(name)?.to_string_lossy().into_owned();
+            Some(Variable { name, value })
+        })
+        .collect();
+
+    let mut findings = Vec::new();
+    let var = |name: &str| {
+        environment
+            .iter()
+            .find(|variable| variable.name == name)
+            .map(|variable| variable.value.as_str())
+    };
+
+    if let Some(xdg) = var("XDG_DATA_HOME") {
+        if cfg!(any(target_os = "macos", windows)) {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: "XDG_DATA_HOME is set, but neither typven nor Typst use it \
+                          on this platform"
+                    .into(),
+                fix: "unset XDG_DATA_HOME".into(),
+            });
+        } else if !Path::new(xdg).is_absolute() {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: format!(
+                    "XDG_DATA_HOME is not an absolute path ({xdg}), so it is ignored"
+                ),
+                fix: "set XDG_DATA_HOME to an absolute path, or unset it".into(),
+            });
+        }
+    }
+
+    for name in ["TYPST_PACKAGE_PATH", "TYPST_PACKAGE_CACHE_PATH"] {
+        if let Some(path) = var(name).filter(|path| Path::new(path).is_relative()) {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: format!(
+                    "{name} is a relative path ({path}), so where Typst looks depends \
+                     on the directory it runs in"
+                ),
+                fix: format!("set {name} to an absolute path"),
+            });
+        }
+    }
+
+    let writable = package_dir.as_deref().is_some_and(writable);
+    match &package_dir {
+        None => findings.push(Finding {
+            severity: Severity::Error,
+            message: "the data directory could not be located".into(),
+            fix: "set the HOME environment variable".into(),
+        }),
+        Some(dir) => {
+            if let Some(path) = var("TYPST_PACKAGE_PATH") {
+                if !same_dir(Path::new(path), dir) {
+                    findings.push(Finding {
+                        severity: Severity::Error,
+                        message: format!(
+                            "Typst reads local packages from TYPST_PACKAGE_PATH \
+                             ({path}), but typven installs them into {}",
+                            dir.display()
+                        ),
+                        fix: format!(
+                            "unset TYPST_PACKAGE_PATH, or set it to {}",
+                            dir.display()
+                        ),
+                    });
+                }
+            }
+
+            if let Some(path) = var("TYPST_PACKAGE_CACHE_PATH") {
+                if same_dir(Path::new(path), dir) {
+                    findings.push(Finding {
+                        severity: Severity::Warning,
+                        message: format!(
+                            "Typst caches downloaded packages in \
+                             TYPST_PACKAGE_CACHE_PATH ({path}), the directory typven \
+                             installs into, so `--all-namespaces` cleans and prunes \
+                             them along with its own"
+                        ),
+                        fix: "set TYPST_PACKAGE_CACHE_PATH to another directory, or \
+                              unset it"
+                            .into(),
+                    });
+                }
+            }
+
+            if !writable {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    message: format!("{} is not writable", dir.display()),
+                    fix: "make it writable, or set XDG_DATA_HOME to a writable directory"
+                        .into(),
+                });
+            }
+        }
+    }
+
+    let git = match git::version() {
+        Ok(version) => Some(version),
+        Err(err) => {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: format!(
+                    "git is not available, so installing with --git fails: {err:#}"
+                ),
+                fix:
+                    "install git and put it on the PATH, or use a typven built with the \
+                      native-git feature"
+                        .into(),
+            });
+            None
+        }
+    };
+
+    let mut namespaces = Vec::new();
+    if let Some(dir) = &package_dir {
+        let names = package::namespaces()?;
+        for name in &names {
+            let installed = package::installed(&dir.join(name));
+            let packages: BTreeSet<_> = installed.iter().map(|p| &p.name).collect();
+            namespaces.push(Namespace {
+                name: name.clone(),
+                packages: packages.len(),
+                versions: installed.len(),
+            });
+        }
+
+        for problem in prune::problems(&names)? {
+            let fix = match problem.removable() {
+                true => "run `typven prune --all-namespaces` to remove it",
+                false => "remove it by hand if it is not needed",
+            };
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: problem.to_string(),
+                fix: fix.into(),
+            });
+        }
+    }
+
+    Ok(Report {
+        typven: env!("CARGO_PKG_VERSION"),
+        data_dir,
+        cache_dir,
+        package_dir,
+        package_cache_dir,
+        writable,
+        environment,
+        git,
+        namespaces,
+        findings,
+    })
+}
+
+/// Whether `a` and `b` are the same directory, resolving symlinks of the
+/// parts that exist.
+fn same_dir(a: &Path, b: &Path) -> bool {
+    let resolve =
+        |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    resolve(a) == resolve(b)
+}
+
+/// Whether packages can be installed into `dir`, or into the nearest parent
+/// that exists if it does not exist yet.
+fn writable(dir: &Path) -> bool {
+    dir.ancestors().find(|dir| dir.is_dir()).is_some_and(|dir| {
+        tempfile::Builder::new()
+            .prefix(".typven-doctor-")
+            .tempfile_in(dir)
+            .is_ok()
+    })
+}
+
+/// Print the report for humans.
+fn print_text(report: &Report) {
+    let path = |path: &Option<PathBuf>| match path {
+        Some(path) => path.display().to_string(),
+        None => "(unknown)".into(),
+    };
+
+    let package_dir = match (&report.package_dir, report.writable) {
+        (Some(dir), _) if !dir.exists() => {
+            format!("{} (created on install)", dir.display())
+        }
+        (Some(dir), true) => format!("{} (writable)", dir.display()),
+        (Some(dir), false) => format!("{} (not writable)", dir.display()),
+        (None, _) => "(unknown)".into(),
+    };
+
+    let mut rows = vec![
+        ("typven", report.typven.to_owned()),
+        ("data directory", path(&report.data_dir)),
+        ("cache directory", path(&report.cache_dir)),
+        ("package directory", package_dir),
+        ("package cache", path(&report.package_cache_dir)),
+    ];
+
+    for name in VARIABLES {
+        let value = report
+            .environment
+            .iter()
+            .find(|variable| variable.name == *name)
+            .map_or("(not set)".to_owned(), |variable| variable.value.clone());
+        rows.push((name, value));
+    }
+
+    rows.push(("git", report.git.clone().unwrap_or_else(|| "(not available)".into())));
+
+    let namespaces: Vec<_> = report
+        .namespaces
+        .iter()
+        .map(|ns| {
+            format!("@{} ({} packages, {} versions)", ns.name, ns.packages, ns.versions)
+        })
+        .collect();
+    rows.push((
+        "namespaces",
+        match namespaces.is_empty() {
+            true => "(none)".into(),
+            false => namespaces.join(", "),
+        },
+    ));
+
+    let width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, value) in rows {
+        println!("{key:width$}  {value}");
+    }
+
+    println!();
+    if report.findings.is_empty() {
+        println!("no problems found");
+    }
+
+    for finding in &report.findings {
+        let severity = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        println!("{severity}: {}", finding.message);
+        println!("  fix: {}", finding.fix);
+    }
+}
@@ -0,0 +1,268 @@
+//! Downloading package archives over HTTP(S).
+//!
+//! Downloads are kept in a content-addressed cache:
+//! `{data-dir}/typven/downloads/sha256/{digest}`
+//! so an archive pinned by its digest is only ever downloaded once, and can be
+//! installed again without network access.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use codespan_reporting::term::{self, termcolor::WriteColor};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{color_stream, error::Error, hash, package};
+
+/// The download cache: `{data-dir}/typven/downloads`.
+pub fn cache_dir() -> anyhow::Result<PathBuf> {
+    Ok(package::data_dir()?.join("typven/downloads"))
+}
+
+/// Fetches the file at `url` into the download cache and returns its path in
+/// the cache together with its SHA-256 digest.
+///
+/// With an `expected` digest, a cached copy is used without touching the
+/// network and a download that does not match is rejected.
+///
+/// # Errors
+///
+/// When the download fails or does not match the `expected` digest.
+pub fn fetch(url: &Url, expected: Option<&str>) -> anyhow::Result<(PathBuf, String)> {
+    fetch_into(&cache_dir()?.join("sha256"), url, expected)
+}
+
+/// Fetches the file at `url` into `dir`, the part of the download cache holding
+/// files by their SHA-256 digest, see [`fetch`].
+fn fetch_into(
+    dir: &Path,
+    url: &Url,
+    expected: Option<&str>,
+) -> anyhow::Result<(PathBuf, String)> {
+    if let Some(expected) = expected {
+        let cached = dir.join(expected);
+        if cached.is_file() && hash::file(&cached)? == expected {
+            print_fetching("using cached", url).ok();
+            return Ok((cached, expected.to_owned()));
+        }
+    }
+
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+
+    // Download next to the cache so the finished file can be renamed into place.
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".typven-download-")
+        .tempfile_in(dir)
+        .context("failed to create a temporary download file")?;
+    print_fetching("downloading", url).ok();
+    let actual = download(url, tmp.as_file_mut())?;
+
+    if let Some(expected) = expected {
+        if actual != expected {
+            bail!(Error::InvalidPackage(format!(
+                "checksum mismatch for {url}, expected sha256 {expected} but got {actual}"
+            )));
+        }
+    }
+
+    let cached = dir.join(&actual);
+    tmp.persist(&cached)
+        .with_context(|| format!("failed to move download to {}", cached.display()))?;
+
+    Ok((cached, actual))
+}
+
+/// Downloads `url` into `file` and returns the SHA-256 digest of its contents.
+fn download(url: &Url, file: &mut File) -> anyhow::Result<String> {
+    let response = match ureq::get(url.as_str()).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => {
+            bail!(Error::NotFound(format!("{url} does not exist")))
+        }
+        Err(ureq::Error::Status(code, _)) => {
+            bail!(Error::Network(format!(
+                "failed to download {url}, server responded with {code}"
+            )))
+        }
+        Err(ureq::Error::Transport(err)) => {
+            let mut reason = err.kind().to_string();
+            if let Some(message) = err.message() {
+                reason = format!("{reason}: {message}");
+            }
+            if let Some(source) = std::error::Error::source(&err) {
+                reason = format!("{reason} ({source})");
+            }
+            bail!(Error::Network(format!("failed to download {url}: {reason}")))
+        }
+    };
+
+    let mut reader = response.into_reader();
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => bail!(Error::Network(format!("failed to download {url}: {err}"))),
+        };
+
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n]).context("failed to write download")?;
+    }
+
+    Ok(hash::hex(&hasher.finalize()))
+}
+
+/// Print that `url` is being fetched, either by downloading it or from the
+/// cache.
+fn print_fetching(action: &str, url: &Url) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_help)?;
+    write!(w, "{action}")?;
+
+    w.reset()?;
+    writeln!(w, " {url}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread::{self, JoinHandle},
+    };
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    /// A stand-in HTTP server that answers the next `requests` requests with
+    /// `body` and then shuts down, so later requests fail to connect.
+    fn serve(body: Vec<u8>, requests: usize) -> (Url, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let url = Url::parse(&format!("http://127.0.0.1:{port}/mypkg.tar.gz")).unwrap();
+
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        (url, server)
+    }
+
+    /// A `.tar.gz` archive of the package `name`.
+    fn archive(name: &str) -> Vec<u8> {
+        let mut builder =
+            tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let manifest = format!(
+            "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n\
+             entrypoint = \"lib.typ\"\n"
+        );
+
+        for (path, contents) in
+            [("typst.toml", manifest.as_str()), ("lib.typ", "#let x = 1\n")]
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, format!("{name}/{path}"), contents.as_bytes())
+                .unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let cache = tempfile::tempdir().unwrap();
+        let (url, server) = serve(archive("mypkg"), 1);
+
+        let expected = "0".repeat(64);
+        let err = fetch_into(cache.path(), &url, Some(&expected)).unwrap_err();
+        server.join().unwrap();
+
+        assert!(
+            matches!(err.downcast_ref::<Error>(), Some(Error::InvalidPackage(_))),
+            "{err:#}"
+        );
+        assert_eq!(fs::read_dir(cache.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn uses_cache_while_offline() {
+        let cache = tempfile::tempdir().unwrap();
+        let body = archive("mypkg");
+        let (url, server) = serve(body.clone(), 1);
+
+        let (path, digest) = fetch_into(cache.path(), &url, None).unwrap();
+        server.join().unwrap();
+        assert_eq!(digest, hash::hex(&Sha256::digest(&body)));
+        assert_eq!(path, cache.path().join(&digest));
+        assert_eq!(fs::read(&path).unwrap(), body);
+
+        // The server is gone, so only the cache can answer.
+        let (cached, _) = fetch_into(cache.path(), &url, Some(&digest)).unwrap();
+        assert_eq!(cached, path);
+        assert!(fetch_into(cache.path(), &url, None).is_err());
+    }
+
+    #[test]
+    fn unpacks_into_package_directory() {
+        use crate::{
+            cli::InstallCommand,
+            install,
+            metadata::{self, Source},
+            testing,
+        };
+
+        let data = testing::data_dir();
+
+        let body = archive("downloaded");
+        let digest = hash::hex(&Sha256::digest(&body));
+        let (url, server) = serve(body, 1);
+
+        let command = InstallCommand {
+            url: Some(url.clone()),
+            sha256: Some(digest.clone()),
+            namespace: "local".into(),
+            ..Default::default()
+        };
+        install::packages(command).unwrap();
+        server.join().unwrap();
+
+        let installed = data.packages().join("local/downloaded/0.1.0");
+        assert_eq!(
+            fs::read_to_string(installed.join("lib.typ")).unwrap(),
+            "#let x = 1\n"
+        );
+        assert!(data.path().join("typven/downloads/sha256").join(&digest).is_file());
+
+        let version = "0.1.0".parse().unwrap();
+        let metadata = metadata::read("local", "downloaded", &version).unwrap();
+        assert_eq!(metadata.source, Source::Url { url: url.to_string(), sha256: digest });
+    }
+}
@@ -0,0 +1,123 @@
+//! Categorized application errors and their process exit codes.
+//!
+//! Every failing command exits with a non-zero status. The exit codes are stable
+//! so shell scripts and Makefiles can react to specific failures:
+//!
+//! | Code | Meaning                                                             |
+//! |------|---------------------------------------------------------------------|
+//! | 0    | Success.                                                            |
+//! | 1    | Any other failure.                                                  |
+//! | 2    | Invalid command-line usage (reported by the argument parser).       |
+//! | 3    | A package, version, release or path could not be found.             |
+//! | 4    | A `typst.toml` manifest, `typven.toml` or `typven.lock` is invalid. |
+//! | 5    | The operation conflicts with the current state.                     |
+//! | 6    | A filesystem operation failed.                                      |
+//! | 7    | A network request failed.                                           |
+//! | 8    | A git operation failed.                                             |
+//! | 9    | A package, or the archive it came in, is invalid.                   |
+
+use std::{fmt, io};
+
+/// Any other failure.
+pub const EXIT_FAILURE: u8 = 1;
+/// Invalid command-line usage.
+pub const EXIT_USAGE: u8 = 2;
+/// A package, version, release or path could not be found.
+pub const EXIT_NOT_FOUND: u8 = 3;
+/// A `typst.toml` manifest, `typven.toml` or `typven.lock` is invalid.
+pub const EXIT_INVALID_MANIFEST: u8 = 4;
+/// The operation conflicts with the current state.
+pub const EXIT_CONFLICT: u8 = 5;
+/// A filesystem operation failed.
+pub const EXIT_IO: u8 = 6;
+/// A network request failed.
+pub const EXIT_NETWORK: u8 = 7;
+/// A git operation failed.
+pub const EXIT_GIT: u8 = 8;
+/// A package, or the archive it came in, is invalid.
+pub const EXIT_INVALID_PACKAGE: u8 = 9;
+
+/// A categorized application error.
+///
+/// Commands return [`anyhow::Result`] and raise these through `bail!` so the
+/// category survives any added context. Use [`exit_code`] to map an error back
+/// to its exit code.
+#[derive(Debug)]
+pub enum Error {
+    /// A package, version, release or path could not be found.
+    NotFound(String),
+    /// A `typst.toml` manifest, `typven.toml` or `typven.lock` is invalid.
+    InvalidManifest(String),
+    /// The operation conflicts with the current state.
+    Conflict(String),
+    /// A filesystem operation failed.
+    Io(String),
+    /// A network request failed.
+    Network(String),
+    /// A git operation failed.
+    Git(String),
+    /// A package, or the archive it came in, is invalid: it is not
+    /// self-contained, escapes its archive or does not match its checksum.
+    InvalidPackage(String),
+}
+
+impl Error {
+    /// The process exit code for this error category.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::NotFound(_) => EXIT_NOT_FOUND,
+            Error::InvalidManifest(_) => EXIT_INVALID_MANIFEST,
+            Error::Conflict(_) => EXIT_CONFLICT,
+            Error::Io(_) => EXIT_IO,
+            Error::Network(_) => EXIT_NETWORK,
+            Error::Git(_) => EXIT_GIT,
+            Error::InvalidPackage(_) => EXIT_INVALID_PACKAGE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(msg)
+            | Error::InvalidManifest(msg)
+            | Error::Conflict(msg)
+            | Error::Io(msg)
+            | Error::Network(msg)
+            | Error::Git(msg)
+            | Error::InvalidPackage(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Determines the exit code for an application-level error.
+///
+/// Walks the error chain and uses the first categorized [`Error`]. Usage errors
+/// found after parsing the command line, and plain I/O and network errors that
+/// were propagated with `?` are categorized as well,
+/// everything else is reported as [`EXIT_FAILURE`].
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<Error>() {
+            return err.exit_code();
+        }
+    }
+
+    for cause in err.chain() {
+        if cause.is::<clap::Error>() {
+            return EXIT_USAGE;
+        }
+
+        if cause.is::<io::Error>() {
+            return EXIT_IO;
+        }
+
+        if cause.is::<ureq::Error>() {
+            return EXIT_NETWORK;
+        }
+    }
+
+    EXIT_FAILURE
+}
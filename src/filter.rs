@@ -0,0 +1,218 @@
+//! Filters that select installed packages by name and version.
+//!
+//! A filter is written as `name[@requirement]` or `name:version`:
+//! - `name` is a glob such as `report-*`, or a regex between slashes such as
+//!   `/^report-\d+$/`.
+//! - `requirement` is a [semver requirement] such as `^1.2` or `<1.0`, as in
+//!   `Cargo.toml`. A bare version such as `1.2.0` means `^1.2.0`.
+//! - `version` is an exact version, as in a Typst import.
+//!
+//! [semver requirement]: https://docs.rs/semver/latest/semver/struct.VersionReq.html
+
+use std::fmt;
+
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use semver::{Version, VersionReq};
+
+/// Selects installed packages by name and, optionally, version.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    /// The filter as written.
+    raw: String,
+    name: Pattern,
+    requirement: Option<VersionReq>,
+}
+
+/// How a [`Filter`] matches package names.
+#[derive(Debug, Clone)]
+enum Pattern {
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Filter {
+    /// Parses a filter written as `name[@requirement]` or `name:version`.
+    pub fn parse(filter: &str) -> Result<Filter, String> {
+        // Requirements never contain a slash, so an `@` followed by one is part
+        // of a regex. A `:` is only taken to start a version when a valid
+        // version follows it.
+        let (name, requirement) = match filter
+            .rsplit_once('@')
+            .filter(|(_, requirement)| !requirement.contains('/'))
+        {
+            Some((name, requirement)) => {
+                let requirement = requirement.parse().map_err(|err| {
+                    format!("`{requirement}` is not a valid version requirement: {err}")
+                })?;
+                (name, Some(requirement))
+            }
+            None => match filter
+                .rsplit_once(':')
+                .filter(|(_, version)| version.parse::<Version>().is_ok())
+            {
+                Some((name, version)) => {
+                    let requirement = format!("={version}").parse().map_err(|err| {
+                        format!("`{version}` is not a valid version: {err}")
+                    })?;
+                    (name, Some(requirement))
+                }
+                None => (filter, None),
+            },
+        };
+
+        let pattern = match name.strip_prefix('/').and_then(|name| name.strip_suffix('/'))
+        {
+            Some(regex) => Pattern::Regex(
+                Regex::new(regex)
+                    .map_err(|err| format!("`{regex}` is not a valid regex: {err}"))?,
+            ),
+            None => Pattern::Glob(
+                Glob::new(name)
+                    .map_err(|err| format!("`{name}` is not a valid glob: {err}"))?
+                    .compile_matcher(),
+            ),
+        };
+
+        Ok(Filter { raw: filter.to_owned(), name: pattern, requirement })
+    }
+
+    /// Narrows a filter without a version requirement down to the exact
+    /// `version`, as if it was written as `name:version`.
+    pub fn exact(self, version: &Version) -> Option<Filter> {
+        if self.requirement.is_some() {
+            return None;
+        }
+
+        Some(Filter {
+            raw: format!("{}:{version}", self.raw),
+            requirement: Some(format!("={version}").parse().ok()?),
+            ..self
+        })
+    }
+
+    /// Whether the package `name` matches, regardless of its version.
+    pub fn matches_name(&self, name: &str) -> bool {
+        match &self.name {
+            Pattern::Glob(glob) => glob.is_match(name),
+            Pattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// Whether the package `name` matches at `version`.
+    pub fn matches(&self, name: &str, version: &Version) -> bool {
+        self.matches_name(name)
+            && self.requirement.as_ref().is_none_or(|req| req.matches(version))
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::cli::{Cli, Command};
+
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    /// Whether `filter` matches each of `packages`, written as `name:version`.
+    fn matches(filter: &str, packages: &[&str]) -> Vec<bool> {
+        let filter = Filter::parse(filter).unwrap();
+        packages
+            .iter()
+            .map(|package| {
+                let (name, v) = package.split_once(':').unwrap();
+                filter.matches(name, &version(v))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_names() {
+        let packages =
+            ["report-a:1.0.0", "report-b:2.0.0", "report:1.0.0", "mypkg:1.0.0"];
+
+        assert_eq!(matches("mypkg", &packages), [false, false, false, true]);
+        assert_eq!(matches("report-*", &packages), [true, true, false, false]);
+        assert_eq!(matches("report*", &packages), [true, true, true, false]);
+        assert_eq!(matches("/^report-[ab]$/", &packages), [true, true, false, false]);
+        assert_eq!(matches("/port/", &packages), [true, true, true, false]);
+        assert_eq!(matches("*", &packages), [true, true, true, true]);
+    }
+
+    #[test]
+    fn parses_versions_and_requirements() {
+        let packages = ["mypkg:0.9.0", "mypkg:1.0.0", "mypkg:1.2.3", "mypkg:2.0.0"];
+
+        assert_eq!(matches("mypkg:1.2.3", &packages), [false, false, true, false]);
+        assert_eq!(matches("mypkg@1.0.0", &packages), [false, true, true, false]);
+        assert_eq!(matches("mypkg@=1.0.0", &packages), [false, true, false, false]);
+        assert_eq!(matches("mypkg@<1.0", &packages), [true, false, false, false]);
+        assert_eq!(matches("mypkg@>=1.2, <3", &packages), [false, false, true, true]);
+        assert_eq!(matches("my*:2.0.0", &packages), [false, false, false, true]);
+        assert_eq!(matches("/^my/@^1", &packages), [false, true, true, false]);
+    }
+
+    #[test]
+    fn keeps_separators_that_are_part_of_the_name() {
+        // An `@` followed by a slash and a `:` not followed by a version are
+        // part of the name.
+        assert!(Filter::parse("/a@b/").unwrap().matches_name("a@b"));
+        assert!(Filter::parse("/a:b/").unwrap().matches_name("xa:by"));
+        assert!(Filter::parse("a:*").unwrap().matches_name("a:1.0.0"));
+        assert!(Filter::parse("a:*").unwrap().matches("a:x", &version("1.0.0")));
+    }
+
+    #[test]
+    fn rejects_invalid_filters() {
+        for filter in ["mypkg@", "mypkg@latest", "mypkg@1.x.y", "/(/", "[a-"] {
+            assert!(Filter::parse(filter).is_err(), "{filter:?}");
+        }
+    }
+
+    #[test]
+    fn narrows_to_exact_versions() {
+        let filter = Filter::parse("report-*").unwrap().exact(&version("1.0.0")).unwrap();
+        assert_eq!(filter.to_string(), "report-*:1.0.0");
+        assert!(filter.matches("report-a", &version("1.0.0")));
+        assert!(!filter.matches("report-a", &version("1.0.1")));
+
+        for filter in ["mypkg:1.0.0", "mypkg@^1"] {
+            let filter = Filter::parse(filter).unwrap();
+            assert!(filter.exact(&version("1.0.0")).is_none());
+        }
+    }
+
+    #[test]
+    fn accepts_versions_after_names_in_clean() {
+        let filters = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["typven", "clean"].iter().chain(args))?;
+            let Command::Clean(command) = cli.command else { unreachable!() };
+            command
+                .filters()
+                .map(|filters| filters.iter().map(Filter::to_string).collect::<Vec<_>>())
+        };
+
+        assert_eq!(filters(&["mypkg", "0.2.5"]).unwrap(), ["mypkg:0.2.5"]);
+        assert_eq!(
+            filters(&["a", "1.0.0", "b", "c", "2.0.0"]).unwrap(),
+            ["a:1.0.0", "b", "c:2.0.0"]
+        );
+        assert_eq!(filters(&["mypkg:0.2.5"]).unwrap(), ["mypkg:0.2.5"]);
+
+        for args in [&["0.2.5"][..], &["mypkg:0.2.4", "0.2.5"], &["a", "1.0.0", "2.0.0"]]
+        {
+            let err = filters(args).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation, "{args:?}");
+        }
+    }
+}
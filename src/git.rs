@@ -0,0 +1,600 @@
+//! Fetching packages from git repositories.
+//!
+//! Repositories are cloned with the `git` executable, which has to be on the
+//! `PATH`, unless typven is built with the `native-git` feature which fetches
+//! them in-process instead. Clones are either made straight from the remote or
+//! from a mirror in the clone cache.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use codespan_reporting::term::{self, termcolor::WriteColor};
+use git_url_parse::GitUrl;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{color_stream, error::Error, hash, package};
+
+/// The revision of a repository to check out, instead of whatever the remote
+/// `HEAD` points to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reference {
+    /// The tip of a branch.
+    Branch(String),
+    /// A tag.
+    Tag(String),
+    /// Any revision `git rev-parse` understands, usually a commit hash.
+    Rev(String),
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Reference::Branch(branch) => write!(f, "branch {branch}"),
+            Reference::Tag(tag) => write!(f, "tag {tag}"),
+            Reference::Rev(rev) => write!(f, "rev {rev}"),
+        }
+    }
+}
+
+/// Clones the repository at `url` into the new directory `dest` and checks out
+/// `reference` or the default branch, returning the full hash of the commit
+/// that was checked out.
+///
+/// Branches, tags and the default branch are cloned shallowly, other revisions
+/// need the full history to be resolved. With `cache`, the repository is
+/// mirrored into the [clone cache](cache_dir) first and checked out from there,
+/// so only new commits are fetched on repeat installs.
+///
+/// # Errors
+///
+/// When the repository can not be cloned or the reference does not exist.
+pub fn clone(
+    url: &Url,
+    reference: Option<&Reference>,
+    dest: &Path,
+    cache: bool,
+) -> anyhow::Result<String> {
+    match cache {
+        true => backend::checkout(url, &update_cache(url)?, reference, dest),
+        false => backend::clone(url, reference, dest),
+    }
+}
+
+/// Describes what repositories are cloned with, such as the version of the
+/// `git` executable.
+///
+/// # Errors
+///
+/// When the `git` executable can not be run.
+pub fn version() -> anyhow::Result<String> {
+    backend::version()
+}
+
+/// The clone cache: `{data-dir}/typven/git`.
+///
+/// Holds a bare mirror of every repository installed from with the cache
+/// enabled, named after the repository and a hash of its url.
+pub fn cache_dir() -> anyhow::Result<PathBuf> {
+    Ok(package::data_dir()?.join("typven/git"))
+}
+
+/// Brings the mirror of the repository at `url` in the clone cache up to date,
+/// creating it if needed, and returns its path.
+///
+/// An existing mirror that can not be updated, for example while offline, is
+/// used as is.
+fn update_cache(url: &Url) -> anyhow::Result<PathBuf> {
+    let name = GitUrl::parse(url.as_str()).map(|repo| repo.name).unwrap_or_default();
+    let key = hash::hex(&Sha256::digest(url.as_str().as_bytes()));
+    let cache_dir = cache_dir()?;
+    let mirror = cache_dir.join(format!("{name}-{}", &key[..16]));
+
+    if !mirror.exists() {
+        fs::create_dir_all(&cache_dir)
+            .with_context(|| format!("failed to create {}", cache_dir.display()))?;
+
+        // Mirror into a temporary directory first so an interrupted clone does
+        // not leave a broken mirror behind.
+        let tmp = tempfile::Builder::new()
+            .prefix(".typven-mirror-")
+            .tempdir_in(&cache_dir)?;
+        let tmp_mirror = tmp.path().join("mirror");
+        backend::mirror(url, &tmp_mirror)?;
+        fs::rename(&tmp_mirror, &mirror)
+            .with_context(|| format!("failed to move mirror to {}", mirror.display()))?;
+
+        return Ok(mirror);
+    }
+
+    if let Err(err) = backend::fetch(&mirror) {
+        print_stale_cache(url, &err).ok();
+    }
+
+    Ok(mirror)
+}
+
+/// Clones repositories with the `git` executable.
+#[cfg(not(feature = "native-git"))]
+mod backend {
+    use std::{path::Path, process::Command};
+
+    use anyhow::bail;
+    use url::Url;
+
+    use super::{missing, Reference};
+    use crate::error::Error;
+
+    /// Clones `url` shallowly into `dest`, see [`super::clone`].
+    pub fn clone(
+        url: &Url,
+        reference: Option<&Reference>,
+        dest: &Path,
+    ) -> anyhow::Result<String> {
+        clone_from(url, url.as_str(), reference, dest, &["--depth", "1"])
+    }
+
+    /// Clones the `mirror` of `url` into `dest`, see [`super::clone`].
+    pub fn checkout(
+        url: &Url,
+        mirror: &Path,
+        reference: Option<&Reference>,
+        dest: &Path,
+    ) -> anyhow::Result<String> {
+        // Clones from a local mirror are cheap and do not support `--depth`.
+        clone_from(url, &mirror.to_string_lossy(), reference, dest, &[])
+    }
+
+    /// Creates a bare mirror of `url` in `dest`.
+    pub fn mirror(url: &Url, dest: &Path) -> anyhow::Result<()> {
+        git(
+            None,
+            &["clone", "--quiet", "--mirror", url.as_str(), &dest.to_string_lossy()],
+        )?;
+        Ok(())
+    }
+
+    /// The version of the `git` executable, as it reports it.
+    pub fn version() -> anyhow::Result<String> {
+        Ok(git(None, &["--version"])?.trim().to_owned())
+    }
+
+    /// Updates every ref of `mirror`.
+    pub fn fetch(mirror: &Path) -> anyhow::Result<()> {
+        git(Some(mirror), &["fetch", "--prune", "--quiet"])?;
+        Ok(())
+    }
+
+    /// Clones `source`, which is `url` or a mirror of it, into `dest` and checks
+    /// out `reference`.
+    fn clone_from(
+        url: &Url,
+        source: &str,
+        reference: Option<&Reference>,
+        dest: &Path,
+        depth: &[&str],
+    ) -> anyhow::Result<String> {
+        let dest_str = dest.to_string_lossy();
+
+        match reference {
+            None => {
+                git(
+                    None,
+                    &[&["clone", "--quiet"], depth, &[source, &dest_str]].concat(),
+                )?;
+            }
+            Some(Reference::Branch(name) | Reference::Tag(name)) => {
+                let args = [
+                    &["clone", "--quiet"],
+                    depth,
+                    &["--branch", name, source, &dest_str],
+                ];
+                git(None, &args.concat())?;
+            }
+            Some(Reference::Rev(rev)) => {
+                git(None, &["clone", "--quiet", "--no-checkout", source, &dest_str])?;
+                let commit = git(
+                    Some(dest),
+                    &["rev-parse", "--verify", &format!("{rev}^{{commit}}")],
+                )
+                .map_err(|_| missing(url, reference))?;
+                git(Some(dest), &["checkout", "--quiet", "--detach", commit.trim()])?;
+            }
+        }
+
+        // `--branch` also accepts tags, make sure a `--branch` is not a tag and the
+        // other way around.
+        match reference {
+            Some(Reference::Branch(name))
+                if !has_ref(dest, &format!("refs/remotes/origin/{name}")) =>
+            {
+                bail!(missing(url, reference));
+            }
+            Some(Reference::Tag(name))
+                if !has_ref(dest, &format!("refs/tags/{name}")) =>
+            {
+                bail!(missing(url, reference));
+            }
+            _ => {}
+        }
+
+        let commit = git(Some(dest), &["rev-parse", "HEAD"])?;
+        Ok(commit.trim().to_owned())
+    }
+
+    /// Whether the repository at `repo` has the fully qualified ref `name`.
+    fn has_ref(repo: &Path, name: &str) -> bool {
+        git(Some(repo), &["show-ref", "--verify", "--quiet", name]).is_ok()
+    }
+
+    /// Runs git with `args`, in the repository at `repo` if given, and returns what
+    /// it wrote to stdout.
+    ///
+    /// # Errors
+    ///
+    /// When git could not be run or exits unsuccessfully, in which case the error
+    /// includes what git wrote to stderr.
+    fn git(repo: Option<&Path>, args: &[&str]) -> anyhow::Result<String> {
+        let mut command = Command::new("git");
+        if let Some(repo) = repo {
+            command.arg("-C").arg(repo);
+        }
+
+        let output = command
+            .args(args)
+            .output()
+            .map_err(|err| Error::Git(format!("failed to run git: {err}")))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!(Error::Git(format!(
+                "`git {}` failed ({}): {}",
+                args.first().copied().unwrap_or_default(),
+                output.status,
+                stderr.trim()
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Clones repositories in-process, without the `git` executable.
+///
+/// Only the files of the checked out commit are written to the destination,
+/// the repository itself is fetched into a temporary directory or the mirror.
+#[cfg(feature = "native-git")]
+mod backend {
+    use std::{
+        fs,
+        num::NonZeroU32,
+        path::{Component, Path},
+        sync::atomic::AtomicBool,
+    };
+
+    use anyhow::{bail, Context};
+    use gix::{
+        objs::tree::EntryKind,
+        progress::Discard,
+        remote::{fetch::Shallow, Direction},
+        traverse::tree::Recorder,
+        ObjectId, Repository,
+    };
+    use url::Url;
+
+    use super::{missing, Reference};
+    use crate::error::Error;
+
+    /// Fetches `url` shallowly and writes the files of `reference` to `dest`, see
+    /// [`super::clone`].
+    pub fn clone(
+        url: &Url,
+        reference: Option<&Reference>,
+        dest: &Path,
+    ) -> anyhow::Result<String> {
+        let git_dir =
+            tempfile::tempdir().context("failed to create a temporary directory")?;
+        let mut prepare = gix::prepare_clone_bare(url.as_str(), git_dir.path())
+            .map_err(|err| Error::Git(format!("failed to clone {url}: {err}")))?;
+
+        // Branches and tags are fetched by their full name, so a branch is never
+        // mistaken for a tag and the other way around.
+        let depth = Shallow::DepthAtRemote(NonZeroU32::new(1).unwrap());
+        prepare = match reference {
+            None => prepare.with_shallow(depth),
+            Some(Reference::Branch(name)) => prepare
+                .with_shallow(depth)
+                .with_ref_name(Some(format!("refs/heads/{name}").as_str()))?,
+            Some(Reference::Tag(name)) => prepare
+                .with_shallow(depth)
+                .with_ref_name(Some(format!("refs/tags/{name}").as_str()))?,
+            Some(Reference::Rev(_)) => prepare,
+        };
+
+        let (repo, _) = prepare.fetch_only(Discard, &AtomicBool::new(false)).map_err(
+            |err| match err {
+                gix::clone::fetch::Error::RefNameMissing { .. } => {
+                    missing(url, reference)
+                }
+                err => Error::Git(format!("failed to clone {url}: {err}")),
+            },
+        )?;
+
+        // The fetched branch or tag is what `HEAD` points to now.
+        let commit = match reference {
+            Some(Reference::Rev(rev)) => resolve(&repo, url, rev, reference)?,
+            _ => resolve(&repo, url, "HEAD", reference)?,
+        };
+
+        write_tree(&repo, commit, dest)?;
+        Ok(commit.to_string())
+    }
+
+    /// Writes the files of `reference` in the `mirror` of `url` to `dest`, see
+    /// [`super::clone`].
+    pub fn checkout(
+        url: &Url,
+        mirror: &Path,
+        reference: Option<&Reference>,
+        dest: &Path,
+    ) -> anyhow::Result<String> {
+        let repo = gix::open(mirror).map_err(|err| {
+            Error::Git(format!("failed to open {}: {err}", mirror.display()))
+        })?;
+
+        let name = match reference {
+            None => "HEAD".to_owned(),
+            Some(Reference::Branch(name)) => format!("refs/heads/{name}"),
+            Some(Reference::Tag(name)) => format!("refs/tags/{name}"),
+            Some(Reference::Rev(rev)) => rev.clone(),
+        };
+
+        let commit = resolve(&repo, url, &name, reference)?;
+        write_tree(&repo, commit, dest)?;
+        Ok(commit.to_string())
+    }
+
+    /// Creates a bare mirror of the branches and tags of `url` in `dest`.
+    pub fn mirror(url: &Url, dest: &Path) -> anyhow::Result<()> {
+        gix::prepare_clone_bare(url.as_str(), dest)
+            .map_err(|err| Error::Git(format!("failed to clone {url}: {err}")))?
+            .configure_remote(|mut remote| {
+                remote.replace_refspecs(
+                    ["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"],
+                    Direction::Fetch,
+                )?;
+                Ok(remote)
+            })
+            .fetch_only(Discard, &AtomicBool::new(false))
+            .map_err(|err| Error::Git(format!("failed to clone {url}: {err}")))?;
+
+        Ok(())
+    }
+
+    /// That git is built in, so no `git` executable is needed.
+    pub fn version() -> anyhow::Result<String> {
+        Ok("built in (native-git)".to_owned())
+    }
+
+    /// Updates every ref of `mirror`.
+    pub fn fetch(mirror: &Path) -> anyhow::Result<()> {
+        let repo = gix::open(mirror)?;
+        let remote = repo
+            .find_default_remote(Direction::Fetch)
+            .context("the mirror has no remote")??;
+
+        remote
+            .connect(Direction::Fetch)?
+            .prepare_fetch(Discard, Default::default())?
+            .receive(Discard, &AtomicBool::new(false))?;
+
+        Ok(())
+    }
+
+    /// Resolves the revision `rev` in `repo` to a commit.
+    fn resolve(
+        repo: &Repository,
+        url: &Url,
+        rev: &str,
+        reference: Option<&Reference>,
+    ) -> anyhow::Result<ObjectId> {
+        let id = repo
+            .rev_parse_single(format!("{rev}^{{commit}}").as_str())
+            .map_err(|_| missing(url, reference))?;
+
+        Ok(id.detach())
+    }
+
+    /// Writes the tree of `commit` to the new directory `dest`.
+    ///
+    /// Submodules are left out, symlinks are only created on Unix and written as
+    /// a file holding their target elsewhere, like git does.
+    fn write_tree(
+        repo: &Repository,
+        commit: ObjectId,
+        dest: &Path,
+    ) -> anyhow::Result<()> {
+        let tree = repo.find_commit(commit)?.tree()?;
+        let mut recorder = Recorder::default();
+        tree.traverse().breadthfirst(&mut recorder)?;
+
+        fs::create_dir(dest)?;
+        for entry in recorder.records {
+            let relative = gix::path::from_bstr(&entry.filepath);
+            if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+                bail!(Error::Git(format!(
+                    "invalid path in repository: {}",
+                    relative.display()
+                )));
+            }
+
+            let path = dest.join(relative);
+            match entry.mode.kind() {
+                EntryKind::Tree => fs::create_dir_all(&path)?,
+                EntryKind::Blob | EntryKind::BlobExecutable => {
+                    fs::write(&path, &repo.find_object(entry.oid)?.data)?
+                }
+                EntryKind::Link => {
+                    let target = repo.find_object(entry.oid)?.detach().data;
+
+                    #[cfg(unix)]
+                    std::os::unix::fs::symlink(
+                        gix::path::from_byte_slice(&target),
+                        &path,
+                    )?;
+
+                    #[cfg(not(unix))]
+                    fs::write(&path, target)?;
+                }
+                EntryKind::Commit => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The error for a `reference` that does not exist in the repository at `url`.
+fn missing(url: &Url, reference: Option<&Reference>) -> Error {
+    Error::Git(match reference {
+        Some(Reference::Branch(name)) => format!("{url} has no branch named `{name}`"),
+        Some(Reference::Tag(name)) => format!("{url} has no tag named `{name}`"),
+        Some(Reference::Rev(rev)) => format!("{url} has no commit `{rev}`"),
+        None => format!("{url} has no default branch"),
+    })
+}
+
+/// Print that the cached mirror of a repository could not be updated.
+fn print_stale_cache(url: &Url, err: &anyhow::Error) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_warning)?;
+    write!(w, "warning")?;
+
+    w.reset()?;
+    writeln!(w, ": failed to update the cached clone of {url}, using it as is: {err}")
+}
+
+/// Abbreviates a commit hash for display.
+pub fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::InstallCommand,
+        install,
+        metadata::Source,
+        testing::{repo, Repo},
+    };
+
+    /// Clones `reference` of `repo`, returning the commit and the version in its
+    /// manifest.
+    fn checkout(
+        repo: &Repo,
+        reference: Option<Reference>,
+    ) -> anyhow::Result<(String, String)> {
+        let dest = repo.dir.path().join("clone");
+        fs::remove_dir_all(&dest).ok();
+
+        let commit = clone(&repo.url, reference.as_ref(), &dest, false)?;
+        let manifest = fs::read_to_string(dest.join("typst.toml")).unwrap();
+        let version = manifest
+            .lines()
+            .find_map(|line| line.strip_prefix("version = "))
+            .unwrap()
+            .trim_matches('"')
+            .to_owned();
+
+        Ok((commit, version))
+    }
+
+    #[test]
+    fn clones_default_branch() {
+        let repo = repo();
+        let (commit, version) = checkout(&repo, None).unwrap();
+        assert_eq!(commit, repo.commits[1]);
+        assert_eq!(version, "0.2.0");
+    }
+
+    #[test]
+    fn clones_branch() {
+        let repo = repo();
+        let (commit, version) =
+            checkout(&repo, Some(Reference::Branch("next".into()))).unwrap();
+        assert_eq!(commit, repo.commits[2]);
+        assert_eq!(version, "0.3.0");
+    }
+
+    #[test]
+    fn clones_tag() {
+        let repo = repo();
+        let (commit, version) =
+            checkout(&repo, Some(Reference::Tag("v0.1.0".into()))).unwrap();
+        assert_eq!(commit, repo.commits[0]);
+        assert_eq!(version, "0.1.0");
+    }
+
+    #[test]
+    fn clones_rev() {
+        let repo = repo();
+        let (commit, version) =
+            checkout(&repo, Some(Reference::Rev(repo.commits[0].clone()))).unwrap();
+        assert_eq!(commit, repo.commits[0]);
+        assert_eq!(version, "0.1.0");
+
+        let short = short(&repo.commits[2]).to_owned();
+        let (commit, version) = checkout(&repo, Some(Reference::Rev(short))).unwrap();
+        assert_eq!(commit, repo.commits[2]);
+        assert_eq!(version, "0.3.0");
+    }
+
+    #[test]
+    fn rejects_missing_references() {
+        let repo = repo();
+        for reference in [
+            Reference::Branch("v0.1.0".into()),
+            Reference::Tag("next".into()),
+            Reference::Branch("missing".into()),
+            Reference::Rev("0000000".into()),
+        ] {
+            assert!(checkout(&repo, Some(reference.clone())).is_err(), "{reference}");
+        }
+    }
+
+    #[test]
+    fn records_checked_out_commit_of_subdir() {
+        let repo = repo();
+        let command = InstallCommand {
+            git: Some(repo.url.clone()),
+            branch: Some("next".into()),
+            subdir: Some("sub".into()),
+            ..Default::default()
+        };
+
+        let (origin, path) = install::resolve(&command).unwrap();
+        let packages = install::find(&path).unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name, "nested");
+
+        match origin.source(&packages[0]) {
+            Source::Git { url, reference, commit, subdir } => {
+                assert_eq!(url, repo.url.to_string());
+                assert_eq!(reference, command.reference());
+                assert_eq!(commit, repo.commits[2]);
+                assert_eq!(subdir, Some(PathBuf::from("sub")));
+            }
+            source => panic!("unexpected source {source:?}"),
+        }
+    }
+}
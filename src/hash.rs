@@ -0,0 +1,91 @@
+//! Content hashing of package trees.
+//!
+//! A tree hash covers the relative path and contents of every file in a
+//! directory, so two trees only hash the same if they contain the same files
+//! with the same contents. Hashes are SHA-256 digests in lowercase hex.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+/// Hashes every file in the tree at `root`.
+///
+/// Symlinks are followed, so a linked package hashes the same as a copy of its
+/// source.
+pub fn tree<P: AsRef<Path>>(root: &P) -> io::Result<String> {
+    let root = root.as_ref();
+    self::files(&root, &list(root)?)
+}
+
+/// Hashes every file in the tree at `root` on its own, keyed by its path
+/// relative to `root` with `/` separators.
+///
+/// Symlinks are followed, like in [`tree`].
+pub fn each<P: AsRef<Path>>(root: &P) -> io::Result<BTreeMap<String, String>> {
+    let root = root.as_ref();
+
+    list(root)?
+        .into_iter()
+        .map(|path| {
+            Ok((path.to_string_lossy().replace('\\', "/"), file(&root.join(&path))?))
+        })
+        .collect()
+}
+
+/// Hashes the contents of the file at `path`.
+pub fn file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Lists every file in the tree at `root`, relative to `root`.
+fn list(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(root).min_depth(1).follow_links(true) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push(entry.path().strip_prefix(root).unwrap().to_path_buf());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Hashes the `files` in the tree at `root`, as if they were the only files in
+/// the tree.
+///
+/// `files` are relative to `root`, the order they are given in does not matter.
+pub fn files<P: AsRef<Path>>(root: &P, files: &[PathBuf]) -> io::Result<String> {
+    let root = root.as_ref();
+
+    // Separators are normalized so a tree hashes the same on every platform.
+    let mut files: Vec<(String, &PathBuf)> = files
+        .iter()
+        .map(|path| (path.to_string_lossy().replace('\\', "/"), path))
+        .collect();
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for (name, path) in files {
+        let contents = fs::read(root.join(path))?;
+
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Encodes `bytes` as lowercase hex.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -9,121 +9,820 @@
 use std::{
     env, fs,
     io::{self, Write},
-    process::Command,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{bail, Context};
-use codespan_reporting::term::{self, termcolor::WriteColor};
-use fs_extra::dir::copy;
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label},
+    files::SimpleFile,
+    term::{self, termcolor::WriteColor},
+};
 use git_url_parse::GitUrl;
+use semver::Version;
+use tempfile::TempDir;
+use url::Url;
 
 use crate::{
+    archive, check,
     cli::InstallCommand,
-    color_stream,
-    package::{self, is_package, Package},
+    color_stream, download,
+    error::Error,
+    git::{self, Reference},
+    hash,
+    metadata::{self, Metadata, Source},
+    package::{self, Candidate, Package},
 };
 
 /// Installs package(s) into the local package directory.
 ///
 /// Attempts to install a single top-level package first and if there is none it
 /// tries to search for packages from your current working directory or the  
-/// given `path` two subdirectories deep.
+/// given `path` two subdirectories deep. Prints a summary of what happened to
+/// each package afterwards.
 ///
 /// # Errors
 ///
 /// Fails if there is no top-level package _and_ it could not find any other
 /// valid packages in or near the current working directory or the given `path`.
 pub fn packages(command: InstallCommand) -> anyhow::Result<()> {
-    let (repo_name, path) = match &command.url {
-        Some(url) => {
-            let repo = GitUrl::parse(url.as_str()).map_err(anyhow::Error::msg)?;
-
-            let path = env::temp_dir();
-            Command::new("git")
-                .args(["-C", path.as_path().to_str().unwrap(), "clone", url.as_str()])
-                .output()
-                .map_err(anyhow::Error::msg)?;
-
-            (Some(repo.name), path)
-        }
-        None => (
-            None,
-            fs::canonicalize(match command.path {
-                Some(path) => path,
-                None => env::current_dir().map_err(anyhow::Error::msg)?,
-            })?,
-        ),
-    };
+    let (origin, path) = resolve(&command)?;
+    let packages = find(&path)?;
+
+    if command.list_files {
+        return packages.iter().try_for_each(|package| list_files(package, &command));
+    }
 
-    let res = {
-        if let Some(package) = is_package(&path) {
-            return install(package);
+    let mut summary = Summary::default();
+    let mut transaction = Transaction::default();
+    for package in packages {
+        let source = origin.source(&package);
+        match install(package, source, &command, &mut transaction) {
+            Ok(outcome) => summary.add(outcome),
+            // Without --atomic only the package that failed is rolled back, as
+            // the ones before it were committed already.
+            Err(err) => {
+                transaction
+                    .rollback()
+                    .with_context(|| format!("failed to roll back after: {err}"))?;
+                return Err(err);
+            }
         }
 
-        let packages = package::search(&path);
+        if !command.atomic {
+            transaction.commit();
+        }
+    }
+
+    summary.print().map_err(Into::into)
+}
+
+/// Resolves where `command` installs from to its [`Origin`] and the directory
+/// to install from.
+///
+/// # Errors
+///
+/// When the path does not exist, or the repository or archive can not be
+/// fetched.
+pub fn resolve(command: &InstallCommand) -> anyhow::Result<(Origin, PathBuf)> {
+    match (&command.git, &command.url) {
+        (Some(url), _) => clone(url, command),
+        (_, Some(url)) => download(url, command),
+        _ => local(command),
+    }
+}
+
+/// Finds the package(s) to install in `path`: either a top-level package or
+/// the valid packages up to two subdirectories deep.
+///
+/// Directories that have a manifest but are not a valid package are reported
+/// as they are skipped.
+///
+/// # Errors
+///
+/// When the top-level manifest is invalid or no valid packages were found.
+pub fn find(path: &Path) -> anyhow::Result<Vec<Package>> {
+    let mut near_misses = 0;
+    let packages = match package::inspect(&path) {
+        Candidate::Valid(package) => vec![*package],
+        Candidate::NoManifest => package::search_candidates(&path)
+            .into_iter()
+            .filter_map(|(_, candidate)| {
+                if candidate.is_near_miss() {
+                    near_misses += 1;
+                    print_near_miss(&candidate).unwrap();
+                }
+
+                candidate.valid()
+            })
+            .collect(),
+        candidate => {
+            print_near_miss(&candidate).unwrap();
+            bail!(Error::InvalidManifest(format!(
+                "{} is not a valid package manifest",
+                path.join("typst.toml").display()
+            )));
+        }
+    };
+
+    if packages.is_empty() {
+        bail!(Error::NotFound(match near_misses {
+            0 => "no valid packages found".into(),
+            n => format!("no valid packages found, {n} manifest(s) were rejected"),
+        }));
+    }
+
+    Ok(packages)
+}
+
+/// Where the package(s) of an installation come from.
+#[derive(Debug)]
+pub enum Origin {
+    /// A directory on the local filesystem.
+    Dir,
+    /// A local or downloaded archive, unpacked into a temporary directory that
+    /// is removed when dropped.
+    Archive { source: Source, _dir: TempDir },
+    /// A cloned repository.
+    Git(Checkout),
+}
 
-        if packages.is_empty() {
-            bail!("no valid packages found");
+impl Origin {
+    /// The source of a `package` found in this origin.
+    pub fn source(&self, package: &Package) -> Source {
+        match self {
+            Origin::Dir => Source::Path { path: package.path.clone() },
+            Origin::Archive { source, .. } => source.clone(),
+            Origin::Git(checkout) => checkout.source(package),
+        }
+    }
+}
+
+/// Resolves the local `path` of `command`, or the current working directory,
+/// to the directory to install from.
+///
+/// Archives are unpacked into a temporary directory first.
+///
+/// # Errors
+///
+/// When the path does not exist, or is a file that is not a supported archive.
+fn local(command: &InstallCommand) -> anyhow::Result<(Origin, PathBuf)> {
+    let path = match command.path.clone() {
+        Some(path) => path,
+        None => {
+            env::current_dir().context("failed to read the current working directory")?
         }
+    };
 
-        for package in packages {
-            install(package)?;
+    let path = fs::canonicalize(&path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => {
+            Error::NotFound(format!("{} does not exist", path.display()))
         }
+        _ => Error::Io(format!("failed to read {}: {err}", path.display())),
+    })?;
 
-        Ok(())
+    if path.is_dir() {
+        return Ok((Origin::Dir, path));
+    }
+
+    let Some(format) = archive::Format::of(&path) else {
+        bail!(Error::InvalidPackage(format!(
+            "{} is neither a directory nor a .tar.gz, .tar.xz or .zip archive",
+            path.display()
+        )));
+    };
+
+    if command.link {
+        bail!(Error::Conflict(format!(
+            "can not link to {}, packages in archives can only be copied",
+            path.display()
+        )));
+    }
+
+    let (dir, root) = unpack(&path, format)?;
+    Ok((Origin::Archive { source: Source::Archive { path }, _dir: dir }, root))
+}
+
+/// Downloads the archive at `url`, verifying it against the digest `command`
+/// pins it to, and unpacks it.
+///
+/// # Errors
+///
+/// When the download fails, does not match its digest or is not a supported
+/// archive.
+fn download(url: &Url, command: &InstallCommand) -> anyhow::Result<(Origin, PathBuf)> {
+    let Some(format) = archive::Format::of(Path::new(url.path())) else {
+        bail!(Error::InvalidPackage(format!(
+            "{url} is not a .tar.gz, .tar.xz or .zip archive"
+        )));
+    };
+
+    let (path, sha256) = download::fetch(url, command.sha256.as_deref())?;
+    if command.sha256.is_none() {
+        println!("sha256 {sha256} (pin it with --sha256)");
+    }
+
+    let (dir, root) = unpack(&path, format)?;
+    let source = Source::Url { url: url.to_string(), sha256 };
+    Ok((Origin::Archive { source, _dir: dir }, root))
+}
+
+/// Unpacks the `archive` into a temporary directory, which is returned along
+/// with the directory to install from.
+fn unpack(archive: &Path, format: archive::Format) -> anyhow::Result<(TempDir, PathBuf)> {
+    print_unpacking(archive).unwrap();
+    // Removed when dropped, whether the installation succeeds or not.
+    let dir = tempfile::Builder::new()
+        .prefix("typven-archive-")
+        .tempdir()
+        .context("failed to create a temporary directory to unpack into")?;
+    let root = archive::unpack(archive, format, &dir.path().join("archive"))?;
+
+    Ok((dir, root))
+}
+
+/// Clones the repository at `url` with the git options of `command` and
+/// resolves the directory to install from.
+///
+/// # Errors
+///
+/// When the repository can not be cloned or the subdirectory does not exist.
+fn clone(url: &Url, command: &InstallCommand) -> anyhow::Result<(Origin, PathBuf)> {
+    let repo = GitUrl::parse(url.as_str())
+        .map_err(|err| Error::Git(format!("invalid repository url: {err}")))?;
+    let reference = command.reference();
+
+    print_cloning(url, reference.as_ref()).unwrap();
+    // Removed when dropped, whether the installation succeeds or not.
+    let dir = tempfile::Builder::new()
+        .prefix("typven-git-")
+        .tempdir()
+        .context("failed to create a temporary directory to clone into")?;
+    let clone = dir.path().join(repo.name);
+    let commit = git::clone(url, reference.as_ref(), &clone, command.git_cache)?;
+    let clone = fs::canonicalize(&clone)?;
+
+    let path = match &command.subdir {
+        Some(subdir) => subdir_path(&clone, subdir)?,
+        None => clone.clone(),
     };
 
-    if res.is_err() {
-        if let Some(repo_name) = repo_name {
-            fs::remove_dir_all(path.join(repo_name))?;
+    let checkout = Checkout {
+        url: url.clone(),
+        reference,
+        commit,
+        path: clone,
+        _dir: dir,
+    };
+    Ok((Origin::Git(checkout), path))
+}
+
+/// A repository cloned to install packages from.
+#[derive(Debug)]
+pub struct Checkout {
+    url: Url,
+    reference: Option<Reference>,
+    commit: String,
+    path: PathBuf,
+    /// The temporary directory `path` is in, removed when the checkout is
+    /// dropped.
+    _dir: TempDir,
+}
+
+impl Checkout {
+    /// The source of a `package` found in this checkout.
+    fn source(&self, package: &Package) -> Source {
+        let subdir = package
+            .path
+            .strip_prefix(&self.path)
+            .ok()
+            .filter(|subdir| !subdir.as_os_str().is_empty())
+            .map(Path::to_path_buf);
+
+        Source::Git {
+            url: self.url.to_string(),
+            reference: self.reference.clone(),
+            commit: self.commit.clone(),
+            subdir,
         }
     }
+}
 
-    res
+/// Resolves `subdir` inside the repository cloned to `clone`.
+///
+/// # Errors
+///
+/// When `subdir` does not exist or points outside of the repository.
+fn subdir_path(clone: &Path, subdir: &Path) -> anyhow::Result<PathBuf> {
+    let path = fs::canonicalize(clone.join(subdir)).map_err(|_| {
+        Error::NotFound(format!("{} does not exist in the repository", subdir.display()))
+    })?;
+
+    if !path.starts_with(fs::canonicalize(clone)?) || !path.is_dir() {
+        bail!(Error::NotFound(format!(
+            "{} is not a directory in the repository",
+            subdir.display()
+        )));
+    }
+
+    Ok(path)
 }
 
-/// Installs a single `Package` into the local package directory.
+/// Installs a single `Package` into the namespace in the local package
+/// directory.
 ///
-/// When the package already exists it will skip the installation.
+/// The package is either copied or, in link mode, symlinked to its source so
+/// edits to the source are picked up without reinstalling. When the package
+/// already exists it will skip the installation, unless it is forced to replace
+/// it or asked to replace it only when its content has changed.
+///
+/// Unless verification is disabled, the package has to be self-contained; see
+/// [`check::verify`].
+///
+/// The package is staged next to the namespaces first and only renamed into
+/// place once it is complete, so Typst never sees a half-written package. The
+/// swap is recorded in `transaction` so it can be rolled back. Afterwards the
+/// `source` of the package and the hashes of its files are recorded in its
+/// [metadata](crate::metadata).
 ///
 /// # Errors
 ///
 /// When access is denied while creating the local package directory structure
-/// or when there are insufficient permissions to copy the packge into /local.
-fn install(package: Package) -> anyhow::Result<()> {
-    let subdir = format!("typst/packages/local/{}/{}", package.name, package.version);
+/// or when there are insufficient permissions to copy the packge into the
+/// namespace.
+pub fn install(
+    package: Package,
+    source: Source,
+    command: &InstallCommand,
+    transaction: &mut Transaction,
+) -> anyhow::Result<Outcome> {
+    let namespace = &command.namespace;
+    let root_dir = package::local_dir()?;
+    let dest = root_dir.join(format!("{namespace}/{}/{}", package.name, package.version));
+
+    let exists = dest.symlink_metadata().is_ok();
+    if exists {
+        if !command.force && !command.update_if_changed {
+            println!("@{namespace}/{package} already exists - skipping");
+            return Ok(Outcome::Skipped);
+        }
 
-    let dest = dirs::data_dir().context("failed to locate /local")?.join(subdir);
+        if command.update_if_changed && !changed(&package, &dest, command.link)? {
+            println!("@{namespace}/{package} is unchanged - skipping");
+            return Ok(Outcome::Unchanged);
+        }
+    }
 
-    if dest.exists() {
-        println!("{}:{} already exists - skipping", package.name, package.version);
-        return Ok(());
+    if !command.no_verify {
+        let problems = check::verify(&package)?;
+        if !problems.is_empty() {
+            check::print_problems(&package, &problems)?;
+            bail!(Error::InvalidPackage(format!(
+                "@{namespace}/{package} is not self-contained, found {} problem(s) \
+                 (use --no-verify to install it anyway)",
+                problems.len()
+            )));
+        }
     }
 
-    let options = fs_extra::dir::CopyOptions {
-        skip_exist: true,
-        content_only: true,
-        ..Default::default()
+    let outcome = match exists {
+        true => Outcome::Replaced,
+        false => Outcome::Installed,
     };
 
-    print_installing(&package).unwrap();
-    fs::create_dir_all(&dest).context("failed to create typst package bundle /local")?;
-    copy(&package.path, &dest, &options).map_err(|err| {
-        fs::remove_dir_all(&dest).ok();
-        err
-    })?;
+    print_installing(outcome, &package, namespace, command.link).unwrap();
+    fs::create_dir_all(dest.parent().unwrap())
+        .with_context(|| format!("failed to create typst package bundle /{namespace}"))?;
+
+    // The staging directory is removed when dropped, so a failed copy does not
+    // leave anything behind.
+    let staging = tempfile::Builder::new()
+        .prefix(STAGING_PREFIX)
+        .tempdir_in(&root_dir)
+        .context("failed to create staging directory")?;
+    let staged = staging.path().join("package");
+
+    if command.link {
+        package::symlink_dir(&package.path, &staged).with_context(|| {
+            format!("failed to link {} to {}", dest.display(), package.path.display())
+        })?;
+    } else {
+        copy(&package, &staged)
+            .with_context(|| format!("failed to copy @{namespace}/{package}"))?;
+    }
+
+    transaction
+        .swap(&root_dir, &staged, dest.clone())
+        .with_context(|| format!("failed to move @{namespace}/{package} into place"))?;
+    transaction.record(namespace, &package, Metadata::new(source, &dest)?)?;
+
+    Ok(outcome)
+}
+
+/// Prefix of the directories packages are staged in before they are moved into
+/// place.
+pub const STAGING_PREFIX: &str = ".typven-staging-";
+
+/// Prefix of the directories replaced packages are kept in until the
+/// installation is committed.
+pub const BACKUP_PREFIX: &str = ".typven-backup-";
+
+/// Package installations that can still be rolled back.
+///
+/// Replaced package versions are kept in a backup directory until the
+/// transaction is committed or dropped.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    steps: Vec<Step>,
+}
+
+/// A single package moved into place by a [`Transaction`].
+#[derive(Debug)]
+struct Step {
+    dest: PathBuf,
+    backup: Option<TempDir>,
+    /// The package whose metadata was replaced, and the metadata it had before.
+    metadata: Option<(String, String, Version, Option<Metadata>)>,
+}
+
+impl Transaction {
+    /// Moves the `staged` package to `dest`, moving any existing package out of
+    /// the way into a backup directory in `root_dir`.
+    fn swap(&mut self, root_dir: &Path, staged: &Path, dest: PathBuf) -> io::Result<()> {
+        let backup = match dest.symlink_metadata() {
+            Ok(_) => {
+                let backup = tempfile::Builder::new()
+                    .prefix(BACKUP_PREFIX)
+                    .tempdir_in(root_dir)?;
+                fs::rename(&dest, backup.path().join("package"))?;
+                Some(backup)
+            }
+            Err(_) => None,
+        };
+
+        if let Err(err) = fs::rename(staged, &dest) {
+            if let Some(backup) = &backup {
+                fs::rename(backup.path().join("package"), &dest).ok();
+            }
+
+            return Err(err);
+        }
+
+        self.steps.push(Step { dest, backup, metadata: None });
+        Ok(())
+    }
+
+    /// Replaces the metadata of the `package` moved into place by the last
+    /// swap.
+    fn record(
+        &mut self,
+        namespace: &str,
+        package: &Package,
+        metadata: Metadata,
+    ) -> anyhow::Result<()> {
+        let previous = metadata::read(namespace, &package.name, &package.version);
+        metadata::write(namespace, &package.name, &package.version, &metadata)?;
+
+        if let Some(step) = self.steps.last_mut() {
+            let (name, version) = (package.name.clone(), package.version.clone());
+            step.metadata = Some((namespace.to_owned(), name, version, previous));
+        }
+
+        Ok(())
+    }
+
+    /// Commits every step so far, deleting the backups of replaced packages.
+    pub fn commit(&mut self) {
+        self.steps.clear();
+    }
+
+    /// Undoes every step in reverse order, removing installed packages and
+    /// restoring the versions and metadata they replaced.
+    pub fn rollback(mut self) -> anyhow::Result<()> {
+        while let Some(step) = self.steps.pop() {
+            print_rolling_back(&step.dest).ok();
+            package::remove_dir(&step.dest)?;
+
+            match &step.backup {
+                Some(backup) => fs::rename(backup.path().join("package"), &step.dest)?,
+                // Only succeeds when no other versions are left in the bundle.
+                None => fs::remove_dir(step.dest.parent().unwrap()).unwrap_or(()),
+            }
+
+            match step.metadata {
+                Some((namespace, name, version, Some(previous))) => {
+                    metadata::write(&namespace, &name, &version, &previous)?
+                }
+                Some((namespace, name, version, None)) => {
+                    metadata::remove(&namespace, &name, Some(&version))?
+                }
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Copies the [files](package::files) of `package` into the new directory
+/// `dest`.
+fn copy(package: &Package, dest: &Path) -> anyhow::Result<()> {
+    fs::create_dir(dest)?;
+
+    for file in package::files(package)? {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(dest.join(parent))?;
+        }
+
+        fs::copy(package.path.join(&file), dest.join(&file))
+            .with_context(|| format!("failed to copy {}", file.display()))?;
+    }
 
     Ok(())
 }
 
-/// Print that a package is being installed.
-fn print_installing(package: &Package) -> io::Result<()> {
+/// Prints the files that would be copied when installing `package`.
+fn list_files(package: &Package, command: &InstallCommand) -> anyhow::Result<()> {
+    println!("@{}/{package}", command.namespace);
+
+    for file in package::files(package)? {
+        println!("  {}", file.to_string_lossy().replace('\\', "/"));
+    }
+
+    Ok(())
+}
+
+/// Whether the installed package at `dest` differs from `package`.
+///
+/// A package that changes between linked and copied always differs, linked
+/// packages differ when they link elsewhere and copied packages differ when the
+/// content hashes of both trees differ.
+fn changed(package: &Package, dest: &Path, link: bool) -> anyhow::Result<bool> {
+    match (package::link_target(&dest), link) {
+        (Some(target), true) => return Ok(target != package.path),
+        (Some(_), false) | (None, true) => return Ok(true),
+        (None, false) => {}
+    }
+
+    let source = hash::files(&package.path, &package::files(package)?)
+        .with_context(|| format!("failed to hash {}", package.path.display()))?;
+    let installed = hash::tree(&dest)
+        .with_context(|| format!("failed to hash {}", dest.display()))?;
+
+    Ok(source != installed)
+}
+
+/// What happened to a single package during installation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The package was not installed before.
+    Installed,
+    /// An existing version was replaced.
+    Replaced,
+    /// An existing version was kept because its content did not change.
+    Unchanged,
+    /// An existing version was kept without checking its content.
+    Skipped,
+}
+
+/// Counts the outcomes of a multi-package installation.
+#[derive(Debug, Default)]
+pub struct Summary {
+    installed: usize,
+    replaced: usize,
+    unchanged: usize,
+    skipped: usize,
+}
+
+impl Summary {
+    /// Record the outcome of a single package.
+    pub fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Installed => self.installed += 1,
+            Outcome::Replaced => self.replaced += 1,
+            Outcome::Unchanged => self.unchanged += 1,
+            Outcome::Skipped => self.skipped += 1,
+        }
+    }
+
+    /// Print the summary.
+    pub fn print(&self) -> io::Result<()> {
+        let mut w = color_stream();
+        let styles = term::Styles::default();
+
+        w.set_color(&styles.header_help)?;
+        write!(w, "summary")?;
+
+        w.reset()?;
+        writeln!(
+            w,
+            ": {} installed, {} replaced, {} unchanged, {} skipped",
+            self.installed, self.replaced, self.unchanged, self.skipped
+        )
+    }
+}
+
+impl FromIterator<Outcome> for Summary {
+    fn from_iter<I: IntoIterator<Item = Outcome>>(iter: I) -> Self {
+        let mut summary = Summary::default();
+        iter.into_iter().for_each(|outcome| summary.add(outcome));
+        summary
+    }
+}
+
+/// Print why a directory with a manifest was not considered a package.
+fn print_near_miss(candidate: &Candidate) -> io::Result<()> {
+    let (manifest, message, span) = match candidate {
+        Candidate::ParseError { manifest, message, span } => {
+            (manifest, message.clone(), span.clone())
+        }
+        Candidate::MissingField { manifest, field } => {
+            (manifest, format!("missing required field `{field}`"), None)
+        }
+        Candidate::Valid(_) | Candidate::NoManifest => return Ok(()),
+    };
+
+    let dir = manifest.parent().unwrap().display();
+    let mut diagnostic =
+        Diagnostic::warning().with_message(format!("skipping {dir}: {message}"));
+
+    let source = fs::read_to_string(manifest).unwrap_or_default();
+    if let Some(span) = span.filter(|span| span.end <= source.len()) {
+        diagnostic = diagnostic.with_labels(vec![Label::primary((), span)]);
+    }
+
+    let file = SimpleFile::new(manifest.display().to_string(), source);
+    term::emit(&mut color_stream(), &term::Config::default(), &file, &diagnostic)
+        .map_err(io::Error::other)
+}
+
+/// Print that an archive is being unpacked.
+fn print_unpacking(path: &Path) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_help)?;
+    write!(w, "unpacking")?;
+
+    w.reset()?;
+    writeln!(w, " {}", path.display())
+}
+
+/// Print that a repository is being cloned.
+fn print_cloning(url: &Url, reference: Option<&Reference>) -> io::Result<()> {
     let mut w = color_stream();
     let styles = term::Styles::default();
 
     w.set_color(&styles.header_help)?;
-    write!(w, "installing")?;
+    write!(w, "cloning")?;
 
     w.reset()?;
-    writeln!(w, " {package}")
+    match reference {
+        Some(reference) => writeln!(w, " {url} ({reference})"),
+        None => writeln!(w, " {url}"),
+    }
+}
+
+/// Print that an installed package is being rolled back.
+fn print_rolling_back(dest: &Path) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_warning)?;
+    write!(w, "rolling back")?;
+
+    w.reset()?;
+    writeln!(w, " {}", dest.display())
+}
+
+/// Print that a package is being installed, linked or replaced.
+fn print_installing(
+    outcome: Outcome,
+    package: &Package,
+    namespace: &str,
+    link: bool,
+) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_help)?;
+    match (outcome, link) {
+        (Outcome::Replaced, _) => write!(w, "replacing")?,
+        (_, true) => write!(w, "linking")?,
+        _ => write!(w, "installing")?,
+    }
+
+    w.reset()?;
+    match link {
+        true => writeln!(w, " @{namespace}/{package} -> {}", package.path.display()),
+        false => writeln!(w, " @{namespace}/{package}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, DataDir};
+
+    use super::*;
+
+    /// Installs the packages in `dir`, replacing the ones installed already.
+    fn install_dir(dir: &Path, atomic: bool) -> anyhow::Result<()> {
+        packages(InstallCommand {
+            path: Some(dir.to_owned()),
+            namespace: "local".into(),
+            force: true,
+            atomic,
+            ..Default::default()
+        })
+    }
+
+    /// Installs `a` from `old`, then tries to install a new `a`, `b` and a `c`
+    /// that is not self-contained.
+    fn fail_third_install(data: &DataDir, atomic: bool) -> anyhow::Error {
+        let old = data.path().join("old");
+        testing::write_package(&old, "a", "0.1.0", "#let version = \"old\"\n");
+        install_dir(&old, false).unwrap();
+
+        let new = data.path().join("new");
+        testing::write_package(&new.join("a"), "a", "0.1.0", "#let version = \"new\"\n");
+        testing::write_package(&new.join("b"), "b", "0.1.0", "");
+        testing::write_package(&new.join("c"), "c", "0.1.0", "#import \"../x.typ\"\n");
+
+        install_dir(&new, atomic).unwrap_err()
+    }
+
+    /// The contents of the entrypoint of `name` and the path it was installed
+    /// from, if it is installed.
+    fn installed(data: &DataDir, name: &str) -> Option<(String, PathBuf)> {
+        let version = Version::new(0, 1, 0);
+        let dir = data.packages().join(format!("local/{name}/{version}"));
+        let contents = fs::read_to_string(dir.join("lib.typ")).ok()?;
+        let Source::Path { path } = metadata::read("local", name, &version)?.source
+        else {
+            return None;
+        };
+
+        Some((contents, path))
+    }
+
+    #[test]
+    fn rolls_back_atomic_installs() {
+        let data = testing::data_dir();
+        let err = fail_third_install(&data, true);
+        assert!(matches!(err.downcast_ref(), Some(Error::InvalidPackage(_))), "{err:#}");
+
+        let old = ("#let version = \"old\"\n".to_owned(), data.path().join("old"));
+        assert_eq!(installed(&data, "a"), Some(old));
+        assert_eq!(installed(&data, "b"), None);
+        assert!(!data.packages().join("local/b").exists());
+        assert!(!data.packages().join("local/c").exists());
+
+        // Neither staging nor backup directories are left behind.
+        let entries: Vec<_> = fs::read_dir(data.packages())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["local"]);
+    }
+
+    #[test]
+    fn commits_every_install_without_atomic() {
+        let data = testing::data_dir();
+        fail_third_install(&data, false);
+
+        let new = data.path().join("new");
+        let a = ("#let version = \"new\"\n".to_owned(), new.join("a"));
+        assert_eq!(installed(&data, "a"), Some(a));
+        assert_eq!(installed(&data, "b"), Some((String::new(), new.join("b"))));
+        assert!(!data.packages().join("local/c").exists());
+    }
+
+    #[test]
+    fn rolls_back_swaps_and_metadata() {
+        let data = testing::data_dir();
+        let root_dir = data.packages();
+        let dest = root_dir.join("local/a/0.1.0");
+        let version = Version::new(0, 1, 0);
+
+        let old = data.path().join("old");
+        testing::write_package(&old, "a", "0.1.0", "old");
+        install_dir(&old, false).unwrap();
+
+        let staged = data.path().join("staged");
+        testing::write_package(&staged, "a", "0.1.0", "new");
+        let package = package::inspect(&staged).valid().unwrap();
+
+        let mut transaction = Transaction::default();
+        transaction.swap(&root_dir, &staged, dest.clone()).unwrap();
+        let source = Source::Path { path: "/new".into() };
+        let metadata = Metadata::new(source.clone(), &dest).unwrap();
+        transaction.record("local", &package, metadata).unwrap();
+
+        assert_eq!(fs::read_to_string(dest.join("lib.typ")).unwrap(), "new");
+        assert_eq!(metadata::read("local", "a", &version).unwrap().source, source);
+
+        transaction.rollback().unwrap();
+        assert_eq!(fs::read_to_string(dest.join("lib.typ")).unwrap(), "old");
+        assert_eq!(installed(&data, "a").unwrap().1, old);
+    }
 }
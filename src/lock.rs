@@ -0,0 +1,241 @@
+//! The `typven.lock` lockfile.
+//!
+//! `typven sync` writes the lockfile next to the [project manifest](crate::project).
+//! It records what every required package resolved to: its version, the exact
+//! source it was installed from and a content hash of the installed tree. Paths
+//! are relative to the lockfile, so it can be checked in and shared between
+//! machines.
+
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, metadata::Source};
+
+/// The file name of the lockfile.
+pub const FILE: &str = "typven.lock";
+
+/// The version of the lockfile format.
+const FORMAT_VERSION: u32 = 1;
+
+/// The header written above the lockfile contents.
+const HEADER: &str =
+    "# This file is generated by `typven sync`, do not edit it by hand.\n";
+
+/// The resolved packages of a project.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    version: u32,
+    /// The locked packages, sorted by namespace and name.
+    #[serde(default, rename = "package")]
+    pub packages: Vec<Locked>,
+}
+
+/// What a required package resolved to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Locked {
+    pub name: String,
+    pub namespace: String,
+    pub version: Version,
+    /// The [tree hash](crate::hash) of the installed package.
+    pub hash: String,
+    pub source: Source,
+}
+
+impl Lockfile {
+    /// A lockfile of the `packages`.
+    pub fn new(mut packages: Vec<Locked>) -> Lockfile {
+        packages.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        Lockfile { version: FORMAT_VERSION, packages }
+    }
+
+    /// The locked package `name` in `namespace`, if any.
+    pub fn get(&self, namespace: &str, name: &str) -> Option<&Locked> {
+        self.packages
+            .iter()
+            .find(|locked| locked.namespace == namespace && locked.name == name)
+    }
+}
+
+impl Locked {
+    /// Locks a package installed from `source`, where `root` is the directory
+    /// of the lockfile.
+    ///
+    /// Local paths in the source are made relative to `root`.
+    pub fn new(
+        namespace: &str,
+        name: &str,
+        version: &Version,
+        hash: String,
+        source: Source,
+        root: &Path,
+    ) -> Locked {
+        let source = match source {
+            Source::Path { path } => Source::Path { path: relative(&path, root) },
+            Source::Archive { path } => Source::Archive { path: relative(&path, root) },
+            source => source,
+        };
+
+        Locked {
+            name: name.to_owned(),
+            namespace: namespace.to_owned(),
+            version: version.clone(),
+            hash,
+            source,
+        }
+    }
+}
+
+/// Reads the lockfile at `path`, or `None` if there is none.
+///
+/// # Errors
+///
+/// When the lockfile can not be read or parsed.
+pub fn read(path: &Path) -> anyhow::Result<Option<Lockfile>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            bail!(Error::Io(format!("failed to read {}: {err}", path.display())))
+        }
+    };
+
+    let lockfile: Lockfile = toml::from_str(&contents).map_err(|err| {
+        Error::InvalidManifest(format!("{} is invalid: {err}", path.display()))
+    })?;
+
+    if lockfile.version != FORMAT_VERSION {
+        bail!(Error::InvalidManifest(format!(
+            "{} has version {}, but this typven only reads version {FORMAT_VERSION}",
+            path.display(),
+            lockfile.version
+        )));
+    }
+
+    Ok(Some(lockfile))
+}
+
+/// Writes the `lockfile` to `path`.
+pub fn write(path: &Path, lockfile: &Lockfile) -> anyhow::Result<()> {
+    let contents = toml::to_string(lockfile).context("failed to serialize lockfile")?;
+    fs::write(path, format!("{HEADER}{contents}"))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// The path of `path` relative to `base`, using `..` where they diverge.
+///
+/// Both paths are expected to be absolute. When they share no root, such as on
+/// different drives on Windows, `path` is returned as is.
+fn relative(path: &Path, base: &Path) -> PathBuf {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+
+    if path_components.peek() != base_components.peek() {
+        return path.to_path_buf();
+    }
+
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+
+    let mut relative: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    relative.extend(path_components);
+
+    match relative.as_os_str().is_empty() {
+        true => PathBuf::from("."),
+        false => relative,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::Reference;
+
+    use super::*;
+
+    #[test]
+    fn relative_paths() {
+        let relative =
+            |path: &str, base: &str| relative(Path::new(path), Path::new(base));
+
+        assert_eq!(relative("/home/me/doc", "/home/me/doc"), Path::new("."));
+        assert_eq!(relative("/home/me/x", "/home/me/doc"), Path::new("../x"));
+        assert_eq!(relative("/home/me/doc/pkgs/x", "/home/me/doc"), Path::new("pkgs/x"));
+        assert_eq!(relative("/opt/x", "/home/me/doc"), Path::new("../../../opt/x"));
+        assert_eq!(relative("/home", "/home/me/doc"), Path::new("../.."));
+        assert_eq!(relative("pkgs/x", "/home/me/doc"), Path::new("pkgs/x"));
+
+        #[cfg(windows)]
+        assert_eq!(relative(r"D:\pkgs\x", r"C:\doc"), Path::new(r"D:\pkgs\x"));
+    }
+
+    #[test]
+    fn round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("doc");
+        let version = Version::new(1, 0, 0);
+
+        let lockfile = Lockfile::new(vec![
+            Locked::new(
+                "local",
+                "mypkg",
+                &version,
+                "a".repeat(64),
+                Source::Path { path: dir.path().join("pkgs/mypkg") },
+                &root,
+            ),
+            Locked::new(
+                "acme",
+                "beta",
+                &version,
+                "b".repeat(64),
+                Source::Git {
+                    url: "https://example.com/beta.git".into(),
+                    reference: Some(Reference::Tag("v1.0.0".into())),
+                    commit: "c".repeat(40),
+                    subdir: Some("pkgs".into()),
+                },
+                &root,
+            ),
+        ]);
+
+        assert_eq!(lockfile.get("acme", "beta").unwrap().name, "beta");
+        assert_eq!(
+            lockfile.get("local", "mypkg").unwrap().source,
+            Source::Path { path: "../pkgs/mypkg".into() }
+        );
+        assert!(lockfile.get("acme", "mypkg").is_none());
+        let names: Vec<_> = lockfile.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["beta", "mypkg"]);
+
+        let path = dir.path().join(FILE);
+        assert_eq!(read(&path).unwrap(), None);
+        write(&path, &lockfile).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().starts_with(HEADER));
+        assert_eq!(read(&path).unwrap(), Some(lockfile));
+    }
+
+    #[test]
+    fn rejects_other_format_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE);
+
+        for contents in ["version = 2\n", "version = \"1\"\n", "[[package]]\n"] {
+            fs::write(&path, contents).unwrap();
+            let err = read(&path).unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(Error::InvalidManifest(_))),
+                "{contents}: {err:#}"
+            );
+        }
+    }
+}
@@ -13,22 +13,125 @@
 //!
 //! # Install package(s) from a given directory
 //! typven install A:/GitHub/my-packages
+//!
+//! # Install package(s) from a .tar.gz, .tar.xz or .zip archive
+//! typven install mypkg-1.0.0.tar.gz
+//!
+//! # Install package(s) from an archive on a server, pinned to its SHA-256 digest
+//! typven install --url https://example.com/mypkg-1.0.0.tar.gz --sha256 <digest>
+//!
+//! # Install package(s) from a repository
+//! typven install --git https://github.com/jimvdl/typven.git
+//!
+//! # Install package(s) from a branch, tag or commit of a repository
+//! typven install --git https://github.com/jimvdl/typven.git --tag v0.2.0
+//!
+//! # Install package(s) from a subdirectory of a repository
+//! typven install --git https://github.com/jimvdl/typven.git --subdir packages
+//!
+//! # Keep a cached clone of the repository for faster repeat installs
+//! typven install --git https://github.com/jimvdl/typven.git --git-cache
+//!
+//! # Install package(s) into the `acme` namespace
+//! typven install --namespace acme
+//!
+//! # Link package(s) under development instead of copying them
+//! typven install --link
+//!
+//! # Replace package versions that are already installed
+//! typven install --force
+//!
+//! # Replace package versions that are already installed when they changed
+//! typven install --update-if-changed
+//!
+//! # Install every package or none of them
+//! typven install --atomic
+//!
+//! # Preview the files that would be copied for each package
+//! typven install --list-files
 //! ```
 //!
+//! The `.git` directory is always left out, as is everything matched by the
+//! `exclude` globs in `typst.toml` or by a `.typvenignore` file in the package
+//! root, which uses gitignore semantics.
+//! Symlinks are copied as the file they link to, and a package with a symlink that
+//! points outside of it is not copied.
+//!
+//! Repositories are cloned with `git`, which has to be on your `PATH` unless typven
+//! was built with the `native-git` feature. Select what to install with `--branch`,
+//! `--tag` or `--rev`, otherwise the default branch is used. typven records the
+//! commit every package was installed from and `ls` and `info` show it.
+//!
+//! Every clone goes into its own temporary directory that is removed afterwards. With
+//! `--git-cache`, typven keeps a mirror of the repository in {data-dir}/typven/git
+//! and clones from it, falling back to the mirror as is when the remote can not be
+//! reached.
+//!
+//! Archives are unpacked into a temporary directory first, the package may sit at
+//! the root of the archive or inside a single top-level folder, like the archives
+//! Typst Universe serves. Archives with entries or symlinks that point outside of
+//! the archive are rejected.
+//!
+//! Archives downloaded with `--url` are checked against the `--sha256` digest and
+//! kept in a content-addressed cache in {data-dir}/typven/downloads, so installing a
+//! pinned archive again does not need the network.
+//!
+//! Before a package is installed, typven verifies that its entrypoint exists and
+//! that the paths it imports and includes stay inside the package. Pass
+//! `--no-verify` to install a package regardless.
+//!
 //! ### Listing
 //!
 //! Viewing the installed packages can be done by running `ls`, this will output
-//! every package with all of their versions in table format.
+//! every package with all of their versions in table format, newest first, along
+//! with where and when each version was installed from.
 //! ```sh
 //! # List installed packages in table format
 //! typven ls
+//!
+//! # List installed packages of every namespace
+//! typven ls --all-namespaces
+//!
+//! # List the packages whose name matches a glob, or a regex between slashes
+//! typven ls 'report-*'
+//! typven ls '/^report-\d+$/'
+//!
+//! # List the versions of a package that match a semver requirement
+//! typven ls mypkg@^1.2
+//!
+//! # List only the newest version of each package
+//! typven ls --latest
+//!
+//! # Sort by name, number of versions, size or when they were last installed
+//! typven ls --sort size
+//!
+//! # List installed packages as JSON, TOML or one import path per line
+//! typven ls --format json
+//! typven ls --format toml
+//! typven ls --format plain
+//! ```
+//!
+//! JSON and TOML hold every package with the path, manifest and install metadata
+//! of each version, for editors and other tooling. An empty list is not an error.
+//!
+//! `info` shows everything about an installed package: the fields of its manifest,
+//! its size on disk, whether it was copied or linked, and where, when and by which
+//! version of typven it was installed.
+//! ```sh
+//! # Show every installed version of a package
+//! typven info mypkg
+//!
+//! # Show a specific version
+//! typven info mypkg:0.2.5
 //! ```
 //!
 //! ### Cleaning
 //!
 //! If you want to clean packages from your system the `clean` subcommand can either
-//! clean the whole directory or target a specific package by either name or name
-//! and version.
+//! clean the whole directory or only the packages that match one or more specs.
+//! Specs are written like `ls` filters: a name, glob or regex, optionally followed
+//! by `:version` for a single version or `@requirement` for a semver requirement.
+//! When a spec matches no installed package, nothing is removed.
 //! ```sh
 //! # Clean all packages
 //! typven clean
@@ -36,8 +139,147 @@
 //! # Clean a specific package
 //! typven clean mypkg
 //!
-//! # Clean a spefic version for a given package
-//! typven clean mypkg 0.2.5
+//! # Clean a specific version of a given package, `typven clean mypkg 0.2.5` works too
+//! typven clean mypkg:0.2.5
+//!
+//! # Clean the versions below 1.0 of a package, and every report package
+//! typven clean 'mypkg@<1.0' 'report-*'
+//!
+//! # Clean all packages in the `acme` namespace
+//! typven clean --namespace acme
+//! ```
+//!
+//! `clean` lists what it removes and how much space that frees, and asks before
+//! removing anything. `--dry-run` only lists, `--yes` skips the question and is
+//! required when `clean` can not ask, such as in scripts. Retention policies keep
+//! some of the versions: `--keep-latest N` keeps the newest N versions of every
+//! package, `--older-than` only removes versions installed longer ago than a
+//! duration such as `12h`, `30d` or `2w`. Directories that fail to be removed are
+//! reported and make `clean` exit with an error.
+//! ```sh
+//! # See what cleaning everything would remove
+//! typven clean --dry-run
+//!
+//! # Keep the two newest versions of every package, without asking
+//! typven clean --keep-latest 2 --yes
+//!
+//! # Remove versions of mypkg installed more than 30 days ago
+//! typven clean mypkg --older-than 30d
+//! ```
+//!
+//! Cleaned package versions are moved to a trash in {data-dir}/typven/trash
+//! rather than deleted, so a mistaken clean can be undone with `restore`. Entries
+//! are kept for 30 days, or as long as the `TYPVEN_TRASH_EXPIRY` environment
+//! variable says (`never` keeps them until the trash is emptied). `clean --no-trash`
+//! deletes package versions for good right away. Package versions in a namespace on
+//! another filesystem, such as a symlinked one, are copied to the trash and back.
+//! ```sh
+//! # Restore the most recently cleaned mypkg:0.2.5
+//! typven restore mypkg:0.2.5
+//!
+//! # Restore everything the trash holds of the `local` namespace
+//! typven restore
+//!
+//! # Restore everything in the trash
+//! typven restore --all-namespaces
+//!
+//! # List what is in the trash
+//! typven trash list
+//!
+//! # Delete what was trashed more than a week ago, or everything
+//! typven trash empty --older-than 7d
+//! typven trash empty
+//! ```
+//!
+//! ### Pruning
+//!
+//! `prune` finds the broken entries in the package directory that `ls` skips:
+//! version folders without a manifest, with an invalid manifest or with a
+//! manifest of another package or version, empty package folders and dangling
+//! symlinks. Version folders are moved to the trash, where `restore` brings them
+//! back, the rest holds nothing and is deleted. Stray files, leftovers of
+//! interrupted installs and metadata of packages that are gone are listed too, but
+//! left for you to remove. Symlinked namespaces and package folders are not looked
+//! into. `prune` takes the same `--dry-run` and `--yes` as `clean`.
+//! ```sh
+//! # See what is broken in every namespace
+//! typven prune --dry-run --all-namespaces
+//!
+//! # Remove the broken entries in the `local` namespace, without asking
+//! typven prune --yes
+//! ```
+//!
+//! ### Checking
+//!
+//! `check` validates `typst.toml` manifests and reports every problem it finds,
+//! pointing at the offending TOML: missing fields, a name that is not in
+//! kebab-case, an invalid SPDX license, a missing entrypoint file, and so on.
+//! ```sh
+//! # Check the manifest(s) in the current working directory
+//! typven check
+//!
+//! # Check the manifest(s) in a given directory
+//! typven check A:/GitHub/my-packages
+//! ```
+//!
+//! ### Syncing
+//!
+//! A document repository can list the packages it needs in a `typven.toml` project
+//! manifest, and `sync` makes the local package directory match it: missing
+//! packages are installed and packages whose content changed are replaced. Either
+//! every package is synced or none of them.
+//! ```toml
+//! # The namespace to install into, `local` if left out
+//! namespace = "acme"
+//!
+//! [packages]
+//! mypkg = { path = "packages/mypkg" }
+//! beta = { git = "https://example.com/beta.git", tag = "v1.0.0", subdir = "pkgs" }
+//! gamma = { url = "https://example.com/gamma-1.0.0.tar.gz", sha256 = "<digest>" }
+//! ```
+//!
+//! Every package is installed from a `path`, relative to the manifest, a `git`
+//! repository with an optional `branch`, `tag`, `rev` and `subdir`, or an archive
+//! `url` with an optional `sha256`. Packages can set their own `namespace`, and a
+//! `version` to pick when their source holds several versions. `sync --prune` moves
+//! the package versions the manifest does not list to the trash, but only in the
+//! namespaces that its packages are installed into.
+//! ```sh
+//! # Sync the packages of the project in the current working directory
+//! typven sync
+//!
+//! # Sync the packages of a given project
+//! typven sync A:/GitHub/my-thesis
+//!
+//! # Also move the package versions the project does not list to the trash
+//! typven sync --prune
+//!
+//! # Only sync when every package resolves to what typven.lock records
+//! typven sync --locked
+//! ```
+//!
+//! `sync` writes a `typven.lock` next to the manifest that records what every
+//! package resolved to: its version, its exact source, such as the commit of a
+//! branch or the digest of an archive, and a content hash of the installed files.
+//! Check it in, and `sync --locked` on another machine refuses to install anything
+//! unless the packages resolve to exactly the same.
+//!
+//! ### Verifying
+//!
+//! typven records the hash of every file it installs, and `verify` hashes the
+//! installed packages again to report the files that were modified, added or went
+//! missing since, for example because someone edited them by hand. Linked packages
+//! are skipped.
+//! ```sh
+//! # Verify every package
+//! typven verify
+//!
+//! # Verify every version of a specific package, or a specific version
+//! typven verify mypkg
+//! typven verify mypkg:0.2.5
+//!
+//! # Reinstall the packages that do not match from where they were installed from
+//! typven verify --restore
 //! ```
 //!
 //! ## Package directory
@@ -47,20 +289,78 @@
 //! - `~/Library/Application Support` on macOS
 //! - `%APPDATA%` on Windows
 //!
+//! typven keeps what it knows about the packages it installed, such as where they
+//! were installed from and the hashes of their files, separately in
+//! {data-dir}/typven/metadata.
+//!
 //! Packages in the data directory have precedence over ones in the cache directory.
 //! While you can create arbitrary namespaces with folders, the namespace typven
-//! uses is `local`:
+//! uses by default is `local`:
 //! - Stores a package in `~/.local/share/typst/packages/local/mypkg/1.0.0`
 //! - Import from it with `#import "@local/mypkg:1.0.0": *`
+//!
+//! `install`, `ls`, `clean`, `verify`, `info`, `restore` and `prune` accept
+//! `--namespace` to use a different namespace, such as `--namespace acme` to import
+//! from `@acme/mypkg:1.0.0`. All of them but `install` also accept
+//! `--all-namespaces` to operate on every namespace at once.
+//!
+//! When an `@local` import fails, `doctor` shows where typven installs packages and
+//! whether Typst looks for them there: the data and cache directories, the
+//! `XDG_DATA_HOME`, `XDG_CACHE_HOME`, `TYPST_PACKAGE_PATH` and
+//! `TYPST_PACKAGE_CACHE_PATH` overrides, whether the package directory is writable,
+//! whether git is available and the namespaces present. Every problem it finds,
+//! including the broken entries `prune` finds, comes with a suggested fix. It exits
+//! with code 5, a conflict, when a problem breaks installing or importing packages.
+//! ```sh
+//! typven doctor
+//!
+//! # The same report as JSON
+//! typven doctor --format json
+//! ```
+//!
+//! ## Exit codes
+//! Every failing command exits with a non-zero status so scripts can react to
+//! specific failures:
+//! - `0`: success.
+//! - `1`: any other failure.
+//! - `2`: invalid command-line usage.
+//! - `3`: a package, version, release or path could not be found.
+//! - `4`: a `typst.toml` manifest, `typven.toml` or `typven.lock` is invalid.
+//! - `5`: the operation conflicts with the current state.
+//! - `6`: a filesystem operation failed.
+//! - `7`: a network request failed.
+//! - `8`: a git operation failed.
+//! - `9`: a package, or the archive it came in, is invalid.
 
+mod archive;
+mod check;
 mod cli;
+mod doctor;
+mod download;
+mod error;
+mod filter;
+mod git;
+mod hash;
 mod install;
+mod lock;
+mod metadata;
 mod package;
+mod project;
+mod prune;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod time;
+mod trash;
 #[cfg(feature = "self-update")]
 mod update;
 mod util;
+mod verify;
 
-use std::io::{self, IsTerminal, Write};
+use std::{
+    io::{self, IsTerminal, Write},
+    process::ExitCode,
+};
 
 use clap::Parser;
 use codespan_reporting::term::{
@@ -70,21 +370,34 @@ use codespan_reporting::term::{
 
 use crate::cli::{Cli, Command};
 
-fn main() -> anyhow::Result<()> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
     let res = match cli.command {
-        Command::Install(command) => install::packages(command),
-        Command::Ls => util::ls(),
+        Command::Install(command) => install::packages(*command),
+        Command::Ls(command) => util::ls(command),
         Command::Update(command) => update::update(command),
         Command::Clean(command) => util::clean(command),
+        Command::Check(command) => check::check(command),
+        Command::Sync(command) => sync::sync(command),
+        Command::Verify(command) => verify::verify(command),
+        Command::Info(command) => util::info(command),
+        Command::Restore(command) => trash::restore(command),
+        Command::Trash(command) => trash::trash(command),
+        Command::Prune(command) => prune::prune(command),
+        Command::Doctor(command) => doctor::doctor(command),
     };
 
-    if let Err(msg) = res {
-        print_error(&msg.to_string())?;
+    if let Err(err) = res {
+        // Usage errors found after parsing are reported like the parser's own.
+        match err.downcast_ref::<clap::Error>() {
+            Some(usage) => usage.print().ok(),
+            None => print_error(&format!("{err:#}")).ok(),
+        };
+        return ExitCode::from(error::exit_code(&err));
     }
 
-    Ok(())
+    ExitCode::SUCCESS
 }
 
 /// Print an application-level error.
@@ -0,0 +1,199 @@
+//! Metadata typven records about the packages it installs.
+//!
+//! It records where and when a package was installed from, by which version of
+//! typven, whether it was copied or linked and the hash of every file that was
+//! installed, so the package can be [verified](crate::verify) later on.
+//! Metadata is kept out of the package directory so installed packages contain
+//! exactly the vendored files. It is stored in:
+//! `{data-dir}/typven/metadata/{namespace}/{name}/{version}.toml`
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use toml::value::Datetime;
+
+use crate::{
+    git::{self, Reference},
+    hash, package, time,
+};
+
+/// What typven knows about an installed package version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Whether the package was copied or linked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<Mode>,
+    /// When the package was installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed: Option<Datetime>,
+    /// The version of typven that installed the package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typven: Option<String>,
+    pub source: Source,
+    /// The SHA-256 digest of every installed file, by its path relative to the
+    /// package root. Linked packages have none.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// The metadata of the package just installed from `source` at `dir`,
+    /// hashing every file in it unless it is linked.
+    pub fn new(source: Source, dir: &Path) -> anyhow::Result<Metadata> {
+        let (mode, files) = match package::link_target(&dir) {
+            Some(_) => (Mode::Link, BTreeMap::new()),
+            None => {
+                let files = hash::each(&dir)
+                    .with_context(|| format!("failed to hash {}", dir.display()))?;
+                (Mode::Copy, files)
+            }
+        };
+
+        Ok(Metadata {
+            mode: Some(mode),
+            installed: Some(time::now()),
+            typven: Some(env!("CARGO_PKG_VERSION").to_owned()),
+            source,
+            files,
+        })
+    }
+}
+
+/// How a package was installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// The files of the package were copied.
+    Copy,
+    /// The package is a symlink to its source.
+    Link,
+}
+
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mode::Copy => f.write_str("copy"),
+            Mode::Link => f.write_str("link"),
+        }
+    }
+}
+
+/// Where an installed package version was installed from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Source {
+    /// A directory on the local filesystem.
+    Path { path: PathBuf },
+    /// An archive on the local filesystem.
+    Archive { path: PathBuf },
+    /// An archive downloaded over HTTP(S).
+    Url { url: String, sha256: String },
+    /// A git repository.
+    Git {
+        url: String,
+        /// The branch, tag or revision that was asked for, if any.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reference: Option<Reference>,
+        /// The full hash of the commit that was installed.
+        commit: String,
+        /// The directory in the repository the package was installed from, if
+        /// it is not the repository root.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        subdir: Option<PathBuf>,
+    },
+}
+
+impl Source {
+    /// The type of the source, as written in the metadata.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Source::Path { .. } => "path",
+            Source::Archive { .. } => "archive",
+            Source::Url { .. } => "url",
+            Source::Git { .. } => "git",
+        }
+    }
+
+    /// A short description of the exact revision that was installed, if the
+    /// source has revisions.
+    pub fn revision(&self) -> Option<String> {
+        match self {
+            Source::Path { .. } | Source::Archive { .. } | Source::Url { .. } => None,
+            Source::Git { commit, .. } => Some(git::short(commit).to_owned()),
+        }
+    }
+}
+
+/// The metadata directory: `{data-dir}/typven/metadata`.
+pub fn dir() -> anyhow::Result<PathBuf> {
+    Ok(package::data_dir()?.join("typven/metadata"))
+}
+
+/// The metadata file of a package version.
+fn file(namespace: &str, name: &str, version: &Version) -> anyhow::Result<PathBuf> {
+    Ok(dir()?.join(format!("{namespace}/{name}/{version}.toml")))
+}
+
+/// Reads the metadata of a package version, or `None` if there is none or it
+/// can not be read.
+///
+/// Packages installed by hand or by an older typven have no metadata.
+pub fn read(namespace: &str, name: &str, version: &Version) -> Option<Metadata> {
+    let contents = fs::read_to_string(file(namespace, name, version).ok()?).ok()?;
+    toml::from_str(&contents).ok()
+}
+
+/// Writes the metadata of a package version, replacing any metadata it had.
+pub fn write(
+    namespace: &str,
+    name: &str,
+    version: &Version,
+    metadata: &Metadata,
+) -> anyhow::Result<()> {
+    let path = file(namespace, name, version)?;
+    fs::create_dir_all(path.parent().unwrap()).with_context(|| {
+        format!("failed to create {}", path.parent().unwrap().display())
+    })?;
+
+    let contents = toml::to_string(metadata).context("failed to serialize metadata")?;
+    fs::write(&path, contents)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Removes the metadata of a package version, or of every version of the
+/// package if `version` is `None`.
+///
+/// Metadata that does not exist is not an error. The package's metadata
+/// directory is removed along with the metadata of its last version.
+pub fn remove(
+    namespace: &str,
+    name: &str,
+    version: Option<&Version>,
+) -> anyhow::Result<()> {
+    let (path, res) = match version {
+        Some(version) => {
+            let path = file(namespace, name, version)?;
+            let res = fs::remove_file(&path);
+            // Only succeeds when no other version has metadata left.
+            fs::remove_dir(path.parent().unwrap()).unwrap_or(());
+            (path, res)
+        }
+        None => {
+            let path = dir()?.join(format!("{namespace}/{name}"));
+            let res = fs::remove_dir_all(&path);
+            (path, res)
+        }
+    };
+
+    match res {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err)
+            .with_context(|| format!("failed to remove metadata {}", path.display())),
+        _ => Ok(()),
+    }
+}
@@ -4,20 +4,29 @@
 //! version in the folder name and manifest must match.
 
 use std::{
-    fs,
+    fs, io,
+    ops::Range,
     path::{Path, PathBuf},
 };
 
+use anyhow::{bail, Context};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
+use crate::error::Error;
+
+/// The namespace typven installs into unless told otherwise.
+pub const DEFAULT_NAMESPACE: &str = "local";
+
 /// A collection of Typst files and assets that can be imported as a unit.
 #[derive(Debug)]
 pub struct Package {
     pub path: PathBuf,
     pub name: String,
     pub version: Version,
+    pub manifest: PackageManifest,
 }
 
 impl std::fmt::Display for Package {
@@ -31,60 +40,528 @@ impl std::fmt::Display for Package {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PackageManifest {
     pub package: PackageSpec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<TemplateSpec>,
+    /// The `[tool]` section, where third-party tools keep their configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<toml::Table>,
 }
 
-/// The `[package]` specification with only the required fields.
+/// The `[package]` specification.
 ///
 /// The compiler requires every package to at least define:
 /// - `name`: The package's identifier in its namespace.
 /// - `version`: The package's version as a full major-minor-patch triple.
-/// Package versioning should follow [SemVer].
+///   Package versioning should follow [SemVer].
 /// - `entrypoint`: The path to the main Typst file that is evaluated when the
-/// package is imported.
+///   package is imported.
+///
+/// Packages can optionally define:
+/// - `authors`: The package's authors, optionally with a contact.
+/// - `license`: The package's license as an [SPDX] expression.
+/// - `description`: A short description of the package.
+/// - `repository`, `homepage`: Links to the package's repository and website.
+/// - `keywords`: Terms to find the package by.
+/// - `categories`, `disciplines`: The package's categories and the disciplines
+///   it is targeted at, from the fixed lists on Typst Universe.
+/// - `compiler`: The minimum compiler version the package requires, such as
+///   `0.11` or `0.11.1`.
+/// - `exclude`: Globs of files that are not part of the package, such as
+///   tests and CI configuration. They are left out when vendoring.
 ///
 /// [SemVer]: https://semver.org/
+/// [SPDX]: https://spdx.github.io/spdx-spec/v2.3/SPDX-license-expressions/
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename = "package")]
 pub struct PackageSpec {
     pub name: String,
     pub version: Version,
     pub entrypoint: PathBuf,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disciplines: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compiler: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+/// The `[template]` specification of packages that can be used as a template.
+///
+/// - `path`: The directory, relative to the package root, that is copied when
+///   a new project is created from the template.
+/// - `entrypoint`: The main file of a new project, relative to `path`.
+/// - `thumbnail`: An optional preview image of the template, relative to the
+///   package root.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "template")]
+pub struct TemplateSpec {
+    pub path: PathBuf,
+    pub entrypoint: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<PathBuf>,
+}
+
+/// The required fields of the `[package]` section.
+const REQUIRED_FIELDS: &[&str] = &["name", "version", "entrypoint"];
+
+/// What [`inspect`] found in a candidate package directory.
+#[derive(Debug)]
+pub enum Candidate {
+    /// The directory contains a valid package.
+    Valid(Box<Package>),
+    /// The directory has no `typst.toml` manifest.
+    NoManifest,
+    /// The manifest could not be read or parsed. The `span` points at the
+    /// problem in the manifest, if it is known.
+    ParseError { manifest: PathBuf, message: String, span: Option<Range<usize>> },
+    /// The manifest lacks a required field, or the `[package]` section.
+    MissingField { manifest: PathBuf, field: String },
+}
+
+impl Candidate {
+    /// The package, if the candidate is valid.
+    pub fn valid(self) -> Option<Package> {
+        match self {
+            Candidate::Valid(package) => Some(*package),
+            _ => None,
+        }
+    }
+
+    /// Whether the candidate has a manifest but is not a valid package, which
+    /// usually means the manifest has a typo.
+    pub fn is_near_miss(&self) -> bool {
+        matches!(self, Candidate::ParseError { .. } | Candidate::MissingField { .. })
+    }
 }
 
-/// Determines if the `path` directory contains a Typst package.
+/// Inspects the `path` directory for a Typst package.
 ///
 /// Only finds a package if:
 /// - `typst.toml` manifest is present in the root directory.
 /// - `typst.toml` contains the [required fields].
+/// - The package name is a valid identifier, see [`is_ident`].
+///
+/// Otherwise reports why the directory is not a package.
 ///
 /// [required fields]: PackageSpec
-pub fn is_package<P: AsRef<Path>>(path: &P) -> Option<Package> {
+pub fn inspect<P: AsRef<Path>>(path: &P) -> Candidate {
     let path = path.as_ref();
+    let manifest = path.join("typst.toml");
 
-    fs::read_to_string(path.join("typst.toml"))
-        .ok()
-        .and_then(|s| toml::from_str(s.as_str()).ok())
-        .map(|m: PackageManifest| Package {
+    let source = match fs::read_to_string(&manifest) {
+        Ok(source) => source,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Candidate::NoManifest
+        }
+        Err(err) => {
+            let message = format!("failed to read manifest: {err}");
+            return Candidate::ParseError { manifest, message, span: None };
+        }
+    };
+
+    let parse_error = |manifest, err: toml::de::Error| Candidate::ParseError {
+        manifest,
+        message: err.message().to_owned(),
+        span: err.span(),
+    };
+
+    let table: toml::Table = match toml::from_str(&source) {
+        Ok(table) => table,
+        Err(err) => return parse_error(manifest, err),
+    };
+
+    let Some(spec) = table.get("package").and_then(toml::Value::as_table) else {
+        return Candidate::MissingField { manifest, field: "package".into() };
+    };
+
+    if let Some(field) = REQUIRED_FIELDS.iter().find(|f| !spec.contains_key(**f)) {
+        return Candidate::MissingField { manifest, field: field.to_string() };
+    }
+
+    match toml::from_str::<PackageManifest>(&source) {
+        // The name ends up in paths, so `../` or `/` must not get through.
+        Ok(m) if !is_ident(&m.package.name) => {
+            let quoted = toml::Value::String(m.package.name.clone()).to_string();
+            let span = source.find(&quoted).map(|start| start..start + quoted.len());
+            let message = format!("`{}` is not a valid package name", m.package.name);
+            Candidate::ParseError { manifest, message, span }
+        }
+        Ok(m) => Candidate::Valid(Box::new(Package {
             path: path.to_path_buf(),
-            name: m.package.name,
-            version: m.package.version,
-        })
+            name: m.package.name.clone(),
+            version: m.package.version.clone(),
+            manifest: m,
+        })),
+        Err(err) => parse_error(manifest, err),
+    }
 }
 
 /// Searches the current `path` and every sub-directory (2 levels deep) for
-/// valid packages. Internally uses [`is_package`] on each directory.
-///
-/// This function cannot fail -- it will simply not include directories that
-/// fail to pass the [`is_package`] test and will only yield valid packages (if
-/// any).
+/// packages, in the order of their paths, so packages are installed in the
+/// same order everywhere. Internally uses [`inspect`] on each directory.
 ///
-/// [`is_package`]: is_package
-pub fn search<P: AsRef<Path>>(path: &P) -> Vec<Package> {
+/// This function cannot fail -- it yields what was found in every directory,
+/// including the directories that are not a valid package.
+pub fn search_candidates<P: AsRef<Path>>(path: &P) -> Vec<(PathBuf, Candidate)> {
     WalkDir::new(path)
         .min_depth(1)
         .max_depth(2)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
-        .filter_map(|e| e.path().is_dir().then(|| is_package(&e.path()))?)
+        .filter(|e| e.path().is_dir())
+        .map(|e| (e.path().to_path_buf(), inspect(&e.path())))
+        .collect()
+}
+
+/// Searches the current `path` and every sub-directory (2 levels deep) for
+/// valid packages. Internally uses [`inspect`] on each directory.
+///
+/// This function cannot fail -- it will simply not include directories that
+/// are not a valid package and will only yield valid packages (if any). Use
+/// [`search_candidates`] to find out why a directory was skipped.
+pub fn search<P: AsRef<Path>>(path: &P) -> Vec<Package> {
+    search_candidates(path)
+        .into_iter()
+        .filter_map(|(_, c)| c.valid())
         .collect()
 }
+
+/// A `{name}/{version}` folder in a namespace, whether it holds a valid package
+/// or not.
+#[derive(Debug)]
+pub struct Installed {
+    pub path: PathBuf,
+    pub name: String,
+    pub version: Version,
+}
+
+impl std::fmt::Display for Installed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.name, self.version)
+    }
+}
+
+/// Lists every `{name}/{version}` folder in the namespace directory `dir`,
+/// sorted by name and version.
+///
+/// Unlike [`search`], this includes folders whose manifest is missing or
+/// broken. Folders whose name is not a version are left out.
+pub fn installed(dir: &Path) -> Vec<Installed> {
+    let mut installed: Vec<Installed> = WalkDir::new(dir)
+        .min_depth(2)
+        .max_depth(2)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| {
+            let version = e.file_name().to_str()?.parse().ok()?;
+            let name = e.path().parent()?.file_name()?.to_str()?.to_owned();
+            Some(Installed { path: e.path().to_path_buf(), name, version })
+        })
+        .collect();
+
+    installed.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    installed
+}
+
+/// The file with additional `exclude` globs, using gitignore semantics.
+pub const IGNORE_FILE: &str = ".typvenignore";
+
+/// Lists the files of `package` that are vendored, relative to its root and
+/// sorted by path.
+///
+/// Leaves out the `.git` directory, the [`IGNORE_FILE`] itself, and everything
+/// matched by the manifest's `exclude` globs or the [`IGNORE_FILE`]. Both use
+/// gitignore semantics, relative to the package root.
+///
+/// Symlinks to files are listed as the file they link to, as long as it is
+/// inside the package. Symlinked directories are not descended into.
+///
+/// # Errors
+///
+/// One of the globs is invalid, the package could not be read or one of its
+/// symlinks points outside of it.
+pub fn files(package: &Package) -> anyhow::Result<Vec<PathBuf>> {
+    let root = package.path.as_path();
+    let ignore = ignore_rules(package)?;
+    let canonical_root = root
+        .canonicalize()
+        .with_context(|| format!("failed to read {}", root.display()))?;
+
+    let mut files = Vec::new();
+    let walker = WalkDir::new(root).min_depth(1).into_iter().filter_entry(|e| {
+        let relative = e.path().strip_prefix(root).unwrap_or(e.path());
+        let is_dir = e.path().is_dir();
+
+        !(is_dir && relative == Path::new(".git"))
+            && relative != Path::new(IGNORE_FILE)
+            && !ignore.matched(relative, is_dir).is_ignore()
+    });
+
+    for entry in walker {
+        let entry =
+            entry.with_context(|| format!("failed to read {}", root.display()))?;
+        if !entry.path().is_file() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(root).unwrap().to_path_buf();
+        if entry.path_is_symlink()
+            && !entry
+                .path()
+                .canonicalize()
+                .is_ok_and(|target| target.starts_with(&canonical_root))
+        {
+            bail!(Error::InvalidPackage(format!(
+                "{} in {package} links to a file outside of the package",
+                relative.display()
+            )));
+        }
+
+        files.push(relative);
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Builds the `exclude` rules of `package` from its manifest and [`IGNORE_FILE`].
+fn ignore_rules(package: &Package) -> anyhow::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(&package.path);
+
+    for glob in &package.manifest.package.exclude {
+        builder
+            .add_line(None, glob)
+            .with_context(|| format!("invalid exclude glob `{glob}` in {package}"))?;
+    }
+
+    let ignore_file = package.path.join(IGNORE_FILE);
+    if ignore_file.is_file() {
+        if let Some(err) = builder.add(&ignore_file) {
+            return Err(err)
+                .with_context(|| format!("invalid glob in {}", ignore_file.display()));
+        }
+    }
+
+    builder
+        .build()
+        .with_context(|| format!("invalid exclude globs in {package}"))
+}
+
+/// The data directory that Typst looks for local packages in and typven keeps
+/// its own files in.
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    #[cfg(test)]
+    if let Some(dir) = crate::testing::overridden_data_dir() {
+        return Ok(dir);
+    }
+
+    dirs::data_dir()
+        .ok_or_else(|| Error::NotFound("failed to locate data directory".into()).into())
+}
+
+/// The local package directory: `{data-dir}/typst/packages`.
+///
+/// Every folder in it is a namespace, holding packages as `{name}/{version}`.
+pub fn local_dir() -> anyhow::Result<PathBuf> {
+    Ok(data_dir()?.join("typst/packages"))
+}
+
+/// Lists every namespace present in the local package directory, sorted by
+/// name.
+///
+/// Folders that are not valid identifiers cannot be imported from, so they
+/// are not considered namespaces.
+pub fn namespaces() -> anyhow::Result<Vec<String>> {
+    let root_dir = local_dir()?;
+
+    let mut namespaces: Vec<String> = match fs::read_dir(&root_dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter(|e| e.path().is_dir())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter(|name| is_ident(name))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    namespaces.sort();
+    Ok(namespaces)
+}
+
+/// Whether `s` is a valid Typst identifier, as required for namespaces and
+/// package names.
+pub fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The target of a linked package, or `None` if `path` is not a symlink.
+pub fn link_target<P: AsRef<Path>>(path: &P) -> Option<PathBuf> {
+    let path = path.as_ref();
+
+    path.symlink_metadata()
+        .ok()
+        .filter(|m| m.file_type().is_symlink())
+        .and_then(|_| fs::read_link(path).ok())
+}
+
+/// The size in bytes of every file in the tree at `path`.
+///
+/// Symlinks are followed, so a linked package is as large as its source.
+pub fn size<P: AsRef<Path>>(path: &P) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in WalkDir::new(path).follow_links(true) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+
+    Ok(size)
+}
+
+/// Creates a directory symlink at `link` pointing to `target`.
+pub fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, link);
+
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_dir(target, link);
+}
+
+/// Removes an installed package directory.
+///
+/// Linked packages only have their link removed, the linked source is never
+/// touched.
+pub fn remove_dir<P: AsRef<Path>>(path: &P) -> io::Result<()> {
+    let path = path.as_ref();
+
+    if link_target(&path).is_none() {
+        return fs::remove_dir_all(path);
+    }
+
+    #[cfg(unix)]
+    return fs::remove_file(path);
+
+    #[cfg(windows)]
+    return fs::remove_dir(path);
+}
+
+/// Moves an installed package directory from `from` to `to`.
+///
+/// When they are on different filesystems, the package is copied over and
+/// removed afterwards instead. Linked packages are moved as the link.
+pub fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {}
+        res => return res,
+    }
+
+    if let Err(err) = copy_tree(from, to) {
+        remove_dir(&to).ok();
+        return Err(err);
+    }
+
+    remove_dir(&from)
+}
+
+/// Copies the tree at `from` to `to`, copying symlinks as symlinks.
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(target) = link_target(&from) {
+        return symlink(&target, to, from.is_dir());
+    }
+
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let dest = to.join(entry.path().strip_prefix(from).unwrap());
+
+        if entry.path_is_symlink() {
+            symlink(&fs::read_link(entry.path())?, &dest, entry.path().is_dir())?;
+        } else if entry.file_type().is_dir() {
+            fs::create_dir(&dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates a symlink at `link` pointing to `target`, which Windows needs to
+/// know is a directory or not.
+fn symlink(target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+    #[cfg(unix)]
+    let _ = is_dir;
+
+    #[cfg(windows)]
+    if !is_dir {
+        return std::os::windows::fs::symlink_file(target, link);
+    }
+
+    symlink_dir(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inspect_rejects_names_that_are_not_identifiers() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for name in ["../../x", "a/b", "/abs", ".", "", "1st"] {
+            let source = format!(
+                "[package]\nname = {}\nversion = \"0.1.0\"\nentrypoint = \"lib.typ\"\n",
+                toml::Value::String(name.into())
+            );
+            fs::write(dir.path().join("typst.toml"), &source).unwrap();
+
+            match inspect(&dir.path()) {
+                Candidate::ParseError { span: Some(span), .. } => {
+                    assert_eq!(source[span].trim_matches('"'), name)
+                }
+                candidate => panic!("`{name}` was accepted: {candidate:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn copies_trees_with_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+
+        fs::create_dir_all(from.join("sub")).unwrap();
+        fs::write(from.join("lib.typ"), "#import \"sub/util.typ\"\n").unwrap();
+        fs::write(from.join("sub/util.typ"), "#let x = 1\n").unwrap();
+        symlink(Path::new("lib.typ"), &from.join("alias.typ"), false).unwrap();
+        symlink(Path::new("sub"), &from.join("alias"), true).unwrap();
+
+        copy_tree(&from, &to).unwrap();
+        assert_eq!(fs::read_to_string(to.join("sub/util.typ")).unwrap(), "#let x = 1\n");
+        assert_eq!(link_target(&to.join("alias.typ")), Some("lib.typ".into()));
+        assert_eq!(link_target(&to.join("alias")), Some("sub".into()));
+
+        // A linked package is copied as the link.
+        let link = dir.path().join("link");
+        symlink_dir(&from, &link).unwrap();
+        copy_tree(&link, &dir.path().join("copied-link")).unwrap();
+        assert_eq!(link_target(&dir.path().join("copied-link")), Some(from));
+    }
+}
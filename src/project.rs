@@ -0,0 +1,351 @@
+//! The `typven.toml` project manifest.
+//!
+//! A document repository lists the packages it needs in a `typven.toml` at its
+//! root, each with where to install it from:
+//! ```toml
+//! # The namespace to install into, `local` if left out.
+//! namespace = "acme"
+//!
+//! [packages]
+//! mypkg = { path = "packages/mypkg" }
+//! beta = { git = "https://example.com/beta.git", tag = "v1.0.0", subdir = "pkgs" }
+//! gamma = { url = "https://example.com/gamma-1.0.0.tar.gz", sha256 = "..." }
+//! delta = { path = "../delta", version = "0.2.0", namespace = "local" }
+//! ```
+//!
+//! Relative paths are relative to the directory of the manifest.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    cli::{self, InstallCommand},
+    error::Error,
+    git::Reference,
+    package::{self, DEFAULT_NAMESPACE},
+};
+
+/// The file name of the project manifest.
+pub const FILE: &str = "typven.toml";
+
+/// A project and the packages it requires.
+#[derive(Debug)]
+pub struct Project {
+    /// The path of the project manifest.
+    pub manifest: PathBuf,
+    /// The required packages, sorted by name.
+    pub packages: Vec<Requirement>,
+}
+
+/// A package required by a project.
+#[derive(Debug)]
+pub struct Requirement {
+    pub name: String,
+    /// The version to install when the source holds several versions of the
+    /// package.
+    pub version: Option<Version>,
+    pub namespace: String,
+    pub source: Spec,
+}
+
+/// Where a required package is installed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Spec {
+    /// A directory or archive on the local filesystem.
+    Path { path: PathBuf },
+    /// A git repository.
+    Git { url: Url, reference: Option<Reference>, subdir: Option<PathBuf> },
+    /// An archive downloaded over HTTP(S), optionally pinned to its digest.
+    Url { url: Url, sha256: Option<String> },
+}
+
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Spec::Path { path } => write!(f, "{}", path.display()),
+            Spec::Git { url, reference: Some(reference), .. } => {
+                write!(f, "{url} ({reference})")
+            }
+            Spec::Git { url, reference: None, .. } | Spec::Url { url, .. } => {
+                write!(f, "{url}")
+            }
+        }
+    }
+}
+
+impl Requirement {
+    /// The install command that installs this package from its source.
+    pub fn command(&self, no_verify: bool) -> InstallCommand {
+        let mut command = InstallCommand {
+            namespace: self.namespace.clone(),
+            update_if_changed: true,
+            atomic: true,
+            no_verify,
+            ..InstallCommand::default()
+        };
+
+        match &self.source {
+            Spec::Path { path } => command.path = Some(path.clone()),
+            Spec::Git { url, reference, subdir } => {
+                command.git = Some(url.clone());
+                command.subdir = subdir.clone();
+                match reference.clone() {
+                    Some(Reference::Branch(branch)) => command.branch = Some(branch),
+                    Some(Reference::Tag(tag)) => command.tag = Some(tag),
+                    Some(Reference::Rev(rev)) => command.rev = Some(rev),
+                    None => {}
+                }
+            }
+            Spec::Url { url, sha256 } => {
+                command.url = Some(url.clone());
+                command.sha256 = sha256.clone();
+            }
+        }
+
+        command
+    }
+}
+
+/// The project manifest as written.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    namespace: Option<String>,
+    #[serde(default)]
+    packages: BTreeMap<String, Entry>,
+}
+
+/// An entry of the `[packages]` table as written.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    path: Option<PathBuf>,
+    git: Option<String>,
+    branch: Option<String>,
+    tag: Option<String>,
+    rev: Option<String>,
+    subdir: Option<PathBuf>,
+    url: Option<String>,
+    sha256: Option<String>,
+    version: Option<Version>,
+    namespace: Option<String>,
+}
+
+/// Reads the project manifest at `path`, which is either the manifest or the
+/// directory it is in.
+///
+/// # Errors
+///
+/// When the manifest does not exist, can not be parsed or one of its packages
+/// has an invalid source.
+pub fn read(path: &Path) -> anyhow::Result<Project> {
+    let manifest = match path.is_dir() {
+        true => path.join(FILE),
+        false => path.to_path_buf(),
+    };
+
+    let source = fs::read_to_string(&manifest).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => {
+            Error::NotFound(format!("{} does not exist", manifest.display()))
+        }
+        _ => Error::Io(format!("failed to read {}: {err}", manifest.display())),
+    })?;
+
+    let parsed: Manifest = toml::from_str(&source).map_err(|err| {
+        Error::InvalidManifest(format!("{} is invalid: {err}", manifest.display()))
+    })?;
+
+    let root = manifest.parent().unwrap_or(Path::new("."));
+    let namespace = parsed.namespace.unwrap_or_else(|| DEFAULT_NAMESPACE.into());
+    let invalid = |message: String| {
+        Error::InvalidManifest(format!("{}: {message}", manifest.display()))
+    };
+
+    cli::parse_namespace(&namespace).map_err(invalid)?;
+
+    let packages = parsed
+        .packages
+        .into_iter()
+        .map(|(name, entry)| requirement(root, &namespace, name, entry).map_err(invalid))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Project { manifest, packages })
+}
+
+/// Validates the `entry` of the package `name` into a [`Requirement`].
+fn requirement(
+    root: &Path,
+    namespace: &str,
+    name: String,
+    entry: Entry,
+) -> Result<Requirement, String> {
+    if !package::is_ident(&name) {
+        return Err(format!("`{name}` is not a valid package name"));
+    }
+
+    let namespace = match entry.namespace {
+        Some(namespace) => cli::parse_namespace(&namespace)?,
+        None => namespace.to_owned(),
+    };
+
+    let reference = match (entry.branch, entry.tag, entry.rev) {
+        (Some(branch), None, None) => Some(Reference::Branch(branch)),
+        (None, Some(tag), None) => Some(Reference::Tag(tag)),
+        (None, None, Some(rev)) => Some(Reference::Rev(rev)),
+        (None, None, None) => None,
+        _ => return Err(format!("`{name}` sets more than one of branch, tag and rev")),
+    };
+
+    let source = match (entry.path, entry.git, entry.url) {
+        (Some(path), None, None) => Spec::Path { path: root.join(path) },
+        (None, Some(git), None) => {
+            let url = Url::parse(&git)
+                .map_err(|err| format!("`{name}` has an invalid git url: {err}"))?;
+            Spec::Git {
+                url,
+                reference: reference.clone(),
+                subdir: entry.subdir.clone(),
+            }
+        }
+        (None, None, Some(url)) => {
+            let url = Url::parse(&url)
+                .map_err(|err| format!("`{name}` has an invalid url: {err}"))?;
+            let sha256 = entry.sha256.as_deref().map(cli::parse_sha256).transpose()?;
+            Spec::Url { url, sha256 }
+        }
+        (None, None, None) => {
+            return Err(format!("`{name}` needs one of path, git or url"))
+        }
+        _ => return Err(format!("`{name}` sets more than one of path, git and url")),
+    };
+
+    if !matches!(source, Spec::Git { .. })
+        && (reference.is_some() || entry.subdir.is_some())
+    {
+        return Err(format!("`{name}` sets branch, tag, rev or subdir without git"));
+    }
+
+    if !matches!(source, Spec::Url { .. }) && entry.sha256.is_some() {
+        return Err(format!("`{name}` sets sha256 without url"));
+    }
+
+    Ok(Requirement { name, version: entry.version, namespace, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a project manifest with `source` from a temporary directory.
+    fn parse(source: &str) -> anyhow::Result<Project> {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(FILE), source).unwrap();
+        read(dir.path())
+    }
+
+    #[test]
+    fn reads_requirements() {
+        let dir = tempfile::tempdir().unwrap();
+        let sha256 = "a".repeat(64);
+        fs::write(
+            dir.path().join(FILE),
+            format!(
+                "namespace = \"acme\"\n\
+                 [packages]\n\
+                 mypkg = {{ path = \"packages/mypkg\" }}\n\
+                 beta = {{ git = \"https://example.com/beta.git\", tag = \"v1.0.0\", \
+                 subdir = \"pkgs\" }}\n\
+                 gamma = {{ url = \"https://example.com/gamma.tar.gz\", \
+                 sha256 = \"{sha256}\" }}\n\
+                 delta = {{ path = \"../delta\", version = \"0.2.0\", \
+                 namespace = \"local\" }}\n"
+            ),
+        )
+        .unwrap();
+
+        let project = read(dir.path()).unwrap();
+        assert_eq!(project.manifest, dir.path().join(FILE));
+
+        let names: Vec<_> = project.packages.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["beta", "delta", "gamma", "mypkg"]);
+        let [beta, delta, gamma, mypkg] = &project.packages[..] else { unreachable!() };
+
+        assert_eq!(mypkg.namespace, "acme");
+        assert_eq!(mypkg.source, Spec::Path { path: dir.path().join("packages/mypkg") });
+        assert_eq!(
+            beta.source,
+            Spec::Git {
+                url: Url::parse("https://example.com/beta.git").unwrap(),
+                reference: Some(Reference::Tag("v1.0.0".into())),
+                subdir: Some("pkgs".into()),
+            }
+        );
+        assert_eq!(
+            gamma.source,
+            Spec::Url {
+                url: Url::parse("https://example.com/gamma.tar.gz").unwrap(),
+                sha256: Some(sha256),
+            }
+        );
+        assert_eq!(delta.namespace, "local");
+        assert_eq!(delta.version, Some(Version::new(0, 2, 0)));
+
+        let project = parse("[packages]\nmypkg = { path = \"mypkg\" }\n").unwrap();
+        assert_eq!(project.packages[0].namespace, DEFAULT_NAMESPACE);
+        assert!(parse("").unwrap().packages.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_requirements() {
+        let sha256 = "a".repeat(64);
+        let git = "git = \"https://example.com/a.git\"";
+        let url = "url = \"https://example.com/a.tar.gz\"";
+
+        for (entry, message) in [
+            (format!("path = \"a\", {git}"), "more than one of path, git and url"),
+            (format!("{git}, {url}"), "more than one of path, git and url"),
+            ("version = \"1.0.0\"".into(), "needs one of path, git or url"),
+            (format!("path = \"a\", sha256 = \"{sha256}\""), "sha256 without url"),
+            (format!("{git}, sha256 = \"{sha256}\""), "sha256 without url"),
+            (format!("{url}, sha256 = \"xyz\""), "not a SHA-256 digest"),
+            ("path = \"a\", branch = \"main\"".into(), "without git"),
+            (format!("{url}, rev = \"abc\""), "without git"),
+            ("path = \"a\", subdir = \"b\"".into(), "without git"),
+            (format!("{git}, branch = \"main\", tag = \"v1\""), "branch, tag and rev"),
+            (format!("{git}, tag = \"v1\", rev = \"abc\""), "branch, tag and rev"),
+            ("git = \"not a url\"".into(), "invalid git url"),
+            ("path = \"a\", namespace = \"1st\"".into(), "not a valid namespace"),
+            ("path = \"a\", registry = \"x\"".into(), "unknown field"),
+        ] {
+            let err = parse(&format!("[packages]\na = {{ {entry} }}\n")).unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(Error::InvalidManifest(_))),
+                "{entry}: {err:#}"
+            );
+            assert!(err.to_string().contains(message), "{entry}: {err:#}");
+        }
+
+        for source in [
+            "namespace = \"not valid\"\n",
+            "[packages]\n\"../a\" = { path = \"a\" }\n",
+            "[packages]\na = \"a\"\n",
+        ] {
+            let err = parse(source).unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(Error::InvalidManifest(_))),
+                "{source}: {err:#}"
+            );
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let err = read(dir.path()).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::NotFound(_))), "{err:#}");
+    }
+}
@@ -0,0 +1,515 @@
+//! Finding and removing broken entries in the local package directory.
+//!
+//! `ls` only looks at `{name}/{version}` folders that hold a valid package and
+//! `clean` only at `{name}/{version}` folders, so anything else in the package
+//! directory goes unseen: folders without a valid manifest, folders whose name
+//! does not match their manifest, empty package folders, dangling symlinks,
+//! stray files, leftovers of interrupted installations and metadata of package
+//! versions that are gone.
+//!
+//! Only the first five are [removable](Problem::removable), the others may be
+//! wanted and are left to be removed by hand. Symlinked namespaces and package
+//! folders are not looked into, as they lead out of the package directory.
+
+use std::{
+    fmt, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::bail;
+use clap::error::ErrorKind;
+use semver::Version;
+
+use crate::{
+    cli::{self, PruneCommand},
+    error::Error,
+    install::{BACKUP_PREFIX, STAGING_PREFIX},
+    metadata,
+    package::{self, Candidate, Installed},
+    trash, util,
+};
+
+/// How long a staging or backup directory has to be left untouched before it
+/// is taken to be a leftover rather than part of an ongoing installation.
+const LEFTOVER_AGE: Duration = Duration::from_secs(3600);
+
+/// A broken entry in the local package directory.
+#[derive(Debug)]
+pub struct Problem {
+    pub path: PathBuf,
+    pub kind: Kind,
+    /// The package version the entry is the folder of, if any, so it is moved
+    /// to the trash as that version.
+    package: Option<(String, String, Version)>,
+}
+
+impl Problem {
+    /// Whether `prune` removes the entry.
+    ///
+    /// Stray files and folders, leftovers of interrupted installations, which
+    /// may hold the only copy of a replaced package version, and orphaned
+    /// metadata are only reported.
+    pub fn removable(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::MissingManifest
+                | Kind::InvalidManifest(_)
+                | Kind::Mismatch { .. }
+                | Kind::EmptyBundle
+                | Kind::DanglingLink(_)
+        )
+    }
+}
+
+/// What is wrong with an entry in the local package directory.
+#[derive(Debug)]
+pub enum Kind {
+    /// A `{name}/{version}` folder without a `typst.toml` manifest.
+    MissingManifest,
+    /// A `{name}/{version}` folder whose manifest is invalid.
+    InvalidManifest(String),
+    /// A `{name}/{version}` folder whose manifest names another package or
+    /// version.
+    Mismatch { name: String, version: Version },
+    /// A package folder without any versions in it.
+    EmptyBundle,
+    /// A symlink whose target no longer exists.
+    DanglingLink(PathBuf),
+    /// A file or folder where a package or version folder is expected.
+    Stray(&'static str),
+    /// A staging or backup directory left behind by an interrupted
+    /// installation.
+    Leftover,
+    /// Metadata of a package version that is not installed.
+    OrphanedMetadata,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path.display())?;
+
+        match &self.kind {
+            Kind::MissingManifest => write!(f, "no typst.toml manifest"),
+            Kind::InvalidManifest(message) => {
+                write!(f, "invalid manifest, {}", message.trim().replace('\n', ", "))
+            }
+            Kind::Mismatch { name, version } => {
+                write!(f, "the manifest is of {name}:{version}")
+            }
+            Kind::EmptyBundle => write!(f, "empty package folder"),
+            Kind::DanglingLink(target) => {
+                write!(f, "dangling link to {}", target.display())
+            }
+            Kind::Stray(what) => write!(f, "{what}"),
+            Kind::Leftover => write!(f, "left behind by an interrupted install"),
+            Kind::OrphanedMetadata => write!(f, "metadata of a package that is gone"),
+        }
+    }
+}
+
+/// Finds and removes broken entries in the local package directory.
+///
+/// Looks through the selected namespace, or every namespace at once, and
+/// lists every broken entry it finds. With `--dry-run` nothing else happens,
+/// otherwise the [removable](Problem::removable) entries are removed after
+/// confirming, unless `--yes` is passed. Version folders are moved to the
+/// [trash](crate::trash), empty folders and dangling symlinks hold nothing and
+/// are deleted.
+///
+/// # Errors
+///
+/// When some of the broken entries could not be removed, or with a usage error
+/// when stdin is not a terminal to confirm on and `--yes` is not passed.
+pub fn prune(command: PruneCommand) -> anyhow::Result<()> {
+    let namespaces = util::target_namespaces(&command.namespace)?;
+    let problems = problems(&namespaces)?;
+
+    if problems.is_empty() {
+        util::print_note("no broken entries found").unwrap();
+        return Ok(());
+    }
+
+    let (removable, kept): (Vec<_>, Vec<_>) =
+        problems.into_iter().partition(Problem::removable);
+    for problem in &removable {
+        println!("{problem}");
+    }
+    for problem in &kept {
+        println!("{problem} (left alone)");
+    }
+
+    if removable.is_empty() {
+        util::print_note("nothing to prune, remove what was left alone by hand").unwrap();
+        return Ok(());
+    }
+
+    let count = removable.len();
+    if command.dry_run {
+        util::print_summary("would prune", &format!("{count} broken entries")).unwrap();
+        return Ok(());
+    }
+
+    if !command.yes {
+        if !io::stdin().is_terminal() {
+            bail!(cli::usage_error(
+                "prune",
+                ErrorKind::MissingRequiredArgument,
+                "stdin is not a terminal to confirm on, pass --yes to prune anyway",
+            ));
+        }
+
+        if !util::confirm(&format!("prune {count} broken entries?"))? {
+            util::print_note("nothing was pruned").unwrap();
+            return Ok(());
+        }
+    }
+
+    trash::expire()?;
+
+    let mut failed = Vec::new();
+    for problem in &removable {
+        if let Err(err) = remove(problem) {
+            failed.push(format!("{}: {err:#}", problem.path.display()));
+        }
+    }
+
+    let removed = count - failed.len();
+    util::print_summary("pruned", &format!("{removed} broken entries")).unwrap();
+
+    if !failed.is_empty() {
+        bail!(Error::Io(format!(
+            "failed to remove {} broken entries:\n  {}",
+            failed.len(),
+            failed.join("\n  ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// Finds every broken entry in the `namespaces` of the local package directory
+/// and their metadata, as well as leftovers of interrupted installations.
+pub fn problems(namespaces: &[String]) -> anyhow::Result<Vec<Problem>> {
+    let root_dir = package::local_dir()?;
+    let metadata_dir = metadata::dir()?;
+
+    let mut problems = leftovers(&root_dir);
+    for namespace in namespaces {
+        let dir = root_dir.join(namespace);
+        if package::link_target(&dir).is_none() {
+            problems.extend(namespace_problems(namespace, &dir));
+        }
+        problems.extend(orphans(namespace, &root_dir, &metadata_dir.join(namespace)));
+    }
+
+    Ok(problems)
+}
+
+/// Finds the staging and backup directories of installations that were
+/// interrupted in the local package directory `root_dir`.
+fn leftovers(root_dir: &Path) -> Vec<Problem> {
+    let now = SystemTime::now();
+
+    entries(root_dir)
+        .into_iter()
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with(STAGING_PREFIX) || name.starts_with(BACKUP_PREFIX)
+        })
+        .filter(|path| {
+            path.symlink_metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| {
+                    now.duration_since(modified).unwrap_or_default() >= LEFTOVER_AGE
+                })
+        })
+        .map(|path| Problem { path, kind: Kind::Leftover, package: None })
+        .collect()
+}
+
+/// Finds the broken entries in the directory `dir` of `namespace`.
+fn namespace_problems(namespace: &str, dir: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for bundle in entries(dir) {
+        let name = bundle.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+        if let Some(problem) = dangling(&bundle, None) {
+            problems.push(problem);
+            continue;
+        }
+
+        if package::link_target(&bundle).is_some() {
+            continue;
+        }
+
+        if !bundle.is_dir() {
+            let kind = Kind::Stray("a file where a package folder is expected");
+            problems.push(Problem { path: bundle, kind, package: None });
+            continue;
+        }
+
+        if !package::is_ident(&name) {
+            let kind = Kind::Stray("not a valid package name");
+            problems.push(Problem { path: bundle, kind, package: None });
+            continue;
+        }
+
+        let versions = entries(&bundle);
+        if versions.is_empty() {
+            problems.push(Problem {
+                path: bundle,
+                kind: Kind::EmptyBundle,
+                package: None,
+            });
+            continue;
+        }
+
+        for path in versions {
+            let version = path
+                .file_name()
+                .and_then(|version| version.to_str())
+                .and_then(|version| version.parse::<Version>().ok());
+            let package =
+                version.clone().map(|v| (namespace.to_owned(), name.clone(), v));
+
+            if let Some(problem) = dangling(&path, package.clone()) {
+                problems.push(problem);
+                continue;
+            }
+
+            let kind = if !path.is_dir() {
+                Kind::Stray("a file where a version folder is expected")
+            } else if package.is_none() {
+                Kind::Stray("not a version")
+            } else {
+                match package::inspect(&path) {
+                    Candidate::Valid(found)
+                        if Some(&found.version) != version.as_ref()
+                            || found.name != name =>
+                    {
+                        Kind::Mismatch { name: found.name, version: found.version }
+                    }
+                    Candidate::Valid(_) => continue,
+                    Candidate::NoManifest => Kind::MissingManifest,
+                    Candidate::ParseError { message, .. } => {
+                        Kind::InvalidManifest(message)
+                    }
+                    Candidate::MissingField { field, .. } => {
+                        Kind::InvalidManifest(format!("missing field `{field}`"))
+                    }
+                }
+            };
+
+            problems.push(Problem { path, kind, package });
+        }
+    }
+
+    problems
+}
+
+/// Finds the metadata in `metadata_dir` of package versions of `namespace`
+/// that are not installed in the local package directory `root_dir`.
+fn orphans(namespace: &str, root_dir: &Path, metadata_dir: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for bundle in entries(metadata_dir) {
+        let name = bundle.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+        for path in entries(&bundle) {
+            let Some(version) = path
+                .file_name()
+                .and_then(|file| file.to_str())
+                .and_then(|file| file.strip_suffix(".toml"))
+            else {
+                continue;
+            };
+
+            let installed = root_dir.join(format!("{namespace}/{name}/{version}"));
+            if installed.symlink_metadata().is_err() {
+                let kind = Kind::OrphanedMetadata;
+                problems.push(Problem { path, kind, package: None });
+            }
+        }
+    }
+
+    problems
+}
+
+/// The problem with `path` if it is a symlink whose target no longer exists.
+fn dangling(path: &Path, package: Option<(String, String, Version)>) -> Option<Problem> {
+    let target = package::link_target(&path)?;
+    if path.exists() {
+        return None;
+    }
+
+    Some(Problem {
+        path: path.to_path_buf(),
+        kind: Kind::DanglingLink(target),
+        package,
+    })
+}
+
+/// The entries in `dir`, sorted by path. A missing `dir` has none.
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir.filter_map(Result::ok).map(|e| e.path()).collect(),
+        Err(_) => Vec::new(),
+    };
+
+    entries.sort();
+    entries
+}
+
+/// Removes the broken entry of `problem`, moving it and its metadata to the
+/// trash if it is the folder of a package version.
+fn remove(problem: &Problem) -> anyhow::Result<()> {
+    let path = &problem.path;
+
+    match (&problem.package, &problem.kind) {
+        (Some((namespace, name, version)), _) => {
+            let installed = Installed {
+                path: path.clone(),
+                name: name.clone(),
+                version: version.clone(),
+            };
+            trash::add(namespace, &installed)?;
+        }
+        // Fails unless the folder is still empty.
+        (None, Kind::EmptyBundle) => fs::remove_dir(path)?,
+        (None, Kind::DanglingLink(_)) => package::remove_dir(path)?,
+        (None, _) => {
+            bail!(Error::Conflict(format!("{} is not removed by prune", path.display())))
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cli::NamespaceArgs,
+        metadata::{Metadata, Source},
+        testing,
+    };
+
+    use super::*;
+
+    /// A namespace with a valid package and one of every broken entry
+    /// `namespace_problems` finds, in the order it reports them.
+    fn broken_namespace(dir: &Path) {
+        let missing = dir.join("missing/0.1.0");
+        fs::create_dir_all(&missing).unwrap();
+        fs::write(missing.join("lib.typ"), "").unwrap();
+
+        testing::write_package(&dir.join("mismatch/0.1.0"), "mismatch", "0.2.0", "");
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        fs::create_dir_all(dir.join("dangling")).unwrap();
+        package::symlink_dir(&dir.join("gone"), &dir.join("dangling/0.1.0")).unwrap();
+        fs::write(dir.join("README.md"), "").unwrap();
+        testing::write_package(&dir.join("valid/0.1.0"), "valid", "0.1.0", "");
+    }
+
+    #[test]
+    fn classifies_broken_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        broken_namespace(dir.path());
+
+        let problems = namespace_problems("local", dir.path());
+        let found: Vec<_> = problems
+            .iter()
+            .map(|problem| {
+                let path = problem.path.strip_prefix(dir.path()).unwrap();
+                (path.to_str().unwrap(), problem.removable())
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("README.md", false),
+                ("dangling/0.1.0", true),
+                ("empty", true),
+                ("mismatch/0.1.0", true),
+                ("missing/0.1.0", true),
+            ]
+        );
+
+        assert!(matches!(problems[0].kind, Kind::Stray(_)));
+        assert!(matches!(problems[1].kind, Kind::DanglingLink(_)));
+        assert!(matches!(problems[2].kind, Kind::EmptyBundle));
+        let version = Version::new(0, 2, 0);
+        assert!(matches!(
+            &problems[3].kind,
+            Kind::Mismatch { name, version: v } if name == "mismatch" && *v == version
+        ));
+        assert!(matches!(problems[4].kind, Kind::MissingManifest));
+    }
+
+    #[test]
+    fn finds_orphaned_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root_dir, metadata_dir) =
+            (dir.path().join("packages"), dir.path().join("meta"));
+
+        testing::write_package(&root_dir.join("local/kept/0.1.0"), "kept", "0.1.0", "");
+        for file in ["kept/0.1.0.toml", "gone/0.1.0.toml"] {
+            let path = metadata_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let problems = orphans("local", &root_dir, &metadata_dir);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].path, metadata_dir.join("gone/0.1.0.toml"));
+        assert!(matches!(problems[0].kind, Kind::OrphanedMetadata));
+        assert!(!problems[0].removable());
+    }
+
+    #[test]
+    fn prunes_only_removable_entries() {
+        let data = testing::data_dir();
+        let dir = data.packages().join("local");
+        broken_namespace(&dir);
+
+        let source = Source::Path { path: "/src/mismatch".into() };
+        let metadata = Metadata::new(source, &dir.join("mismatch/0.1.0")).unwrap();
+        metadata::write("local", "mismatch", &Version::new(0, 1, 0), &metadata).unwrap();
+
+        let namespace =
+            NamespaceArgs { namespace: "local".into(), all_namespaces: false };
+        prune(PruneCommand { dry_run: false, yes: true, namespace }).unwrap();
+
+        let left: Vec<_> = entries(&dir)
+            .into_iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(left, ["README.md", "valid"]);
+
+        let mut trashed: Vec<_> = trash::entries().unwrap();
+        trashed.sort_by(|a, b| a.name.cmp(&b.name));
+        let trashed: Vec<_> = trashed.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(trashed, ["dangling", "mismatch", "missing"]);
+        assert!(metadata::read("local", "mismatch", &Version::new(0, 1, 0)).is_none());
+    }
+
+    #[test]
+    fn requires_yes_without_a_terminal() {
+        if io::stdin().is_terminal() {
+            return;
+        }
+
+        let data = testing::data_dir();
+        let dir = data.packages().join("local");
+        broken_namespace(&dir);
+
+        let namespace =
+            NamespaceArgs { namespace: "local".into(), all_namespaces: false };
+        let err =
+            prune(PruneCommand { dry_run: false, yes: false, namespace }).unwrap_err();
+        assert_eq!(crate::error::exit_code(&err), crate::error::EXIT_USAGE);
+        assert!(dir.join("missing/0.1.0").is_dir());
+    }
+}
@@ -0,0 +1,421 @@
+//! Syncing the local package directory with a [project manifest](crate::project).
+
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use codespan_reporting::term::{self, termcolor::WriteColor};
+use semver::Version;
+
+use crate::{
+    cli::{InstallCommand, SyncCommand},
+    color_stream,
+    error::Error,
+    hash,
+    install::{self, Origin, Summary, Transaction},
+    lock::{self, Locked, Lockfile},
+    metadata::{self, Metadata, Source},
+    package::{self, Installed, Package},
+    project::{self, Requirement},
+    trash,
+};
+
+/// Makes the local package directory match the project manifest.
+///
+/// Resolves every package the manifest lists first, then installs the ones that
+/// are missing and replaces the ones whose content changed, all or nothing:
+/// when one package fails, the packages synced before it are rolled back. With
+/// `--prune`, package versions in the namespaces the manifest installs into
+/// that it does not list are moved to the [trash](crate::trash) afterwards.
+///
+/// What the packages resolved to is written to the [lockfile](crate::lock). With
+/// `--locked`, nothing is installed unless they resolve to exactly what the
+/// lockfile records.
+///
+/// # Errors
+///
+/// When the manifest can not be read, one of its packages can not be found in
+/// or installed from its source, or the lockfile is out of date with
+/// `--locked`.
+pub fn sync(command: SyncCommand) -> anyhow::Result<()> {
+    let path = match &command.path {
+        Some(path) => path.clone(),
+        None => {
+            env::current_dir().context("failed to read the current working directory")?
+        }
+    };
+
+    let project = project::read(&path)?;
+    let lock_path = project.manifest.with_file_name(lock::FILE);
+    let previous = lock::read(&lock_path)?;
+    if command.locked && previous.is_none() {
+        bail!(Error::NotFound(format!(
+            "{} does not exist, run `typven sync` without --locked to create it",
+            lock_path.display()
+        )));
+    }
+
+    print_syncing(&project.manifest).unwrap();
+
+    let locked = previous.as_ref().filter(|_| command.locked);
+    let resolved = project
+        .packages
+        .iter()
+        .map(|requirement| resolve(requirement, &command, locked))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let root = fs::canonicalize(lock_path.parent().unwrap_or(Path::new(".")))
+        .context("failed to read the project directory")?;
+    let lockfile = Lockfile::new(resolved.iter().map(|r| r.locked(&root)).collect());
+
+    if let Some(locked) = locked {
+        let outdated = outdated(locked, &lockfile);
+        if !outdated.is_empty() {
+            bail!(Error::Conflict(format!(
+                "{} is out of date for {}, run `typven sync` without --locked to \
+                 update it",
+                lock_path.display(),
+                outdated.join(", ")
+            )));
+        }
+    }
+
+    let mut summary = Summary::default();
+    let mut transaction = Transaction::default();
+    let mut synced = BTreeSet::new();
+    for resolved in resolved {
+        let key = (
+            resolved.requirement.namespace.clone(),
+            resolved.requirement.name.clone(),
+            resolved.package.version.clone(),
+        );
+
+        match resolved.install(&mut transaction) {
+            Ok(outcome) => {
+                summary.add(outcome);
+                synced.insert(key);
+            }
+            Err(err) => {
+                transaction
+                    .rollback()
+                    .with_context(|| format!("failed to roll back after: {err}"))?;
+                return Err(err);
+            }
+        }
+    }
+
+    transaction.commit();
+
+    if command.prune {
+        let namespaces: BTreeSet<_> = project
+            .packages
+            .iter()
+            .map(|requirement| requirement.namespace.as_str())
+            .collect();
+        prune(&namespaces, &synced)?;
+    }
+
+    if previous.as_ref() != Some(&lockfile) {
+        lock::write(&lock_path, &lockfile)?;
+    }
+
+    summary.print().map_err(Into::into)
+}
+
+/// A required package, resolved to the package it selects in its source.
+struct Resolved<'a> {
+    requirement: &'a Requirement,
+    command: InstallCommand,
+    /// Keeps the clone or unpacked archive the package is in around.
+    _origin: Origin,
+    package: Package,
+    source: Source,
+    /// The [tree hash](crate::hash) of the files that will be installed.
+    hash: String,
+}
+
+/// Fetches the source of `requirement` and selects the package it asks for.
+///
+/// An archive `url` without a digest is checked against the digest in the
+/// `locked` lockfile, if there is one.
+fn resolve<'a>(
+    requirement: &'a Requirement,
+    command: &SyncCommand,
+    locked: Option<&Lockfile>,
+) -> anyhow::Result<Resolved<'a>> {
+    let mut command = requirement.command(command.no_verify);
+    if command.url.is_some() && command.sha256.is_none() {
+        command.sha256 = locked
+            .and_then(|locked| locked.get(&requirement.namespace, &requirement.name))
+            .and_then(|locked| match &locked.source {
+                Source::Url { sha256, .. } => Some(sha256.clone()),
+                _ => None,
+            });
+    }
+
+    let (origin, path) = install::resolve(&command)?;
+    let package = select(requirement, install::find(&path)?)?;
+    let source = origin.source(&package);
+    let hash = hash::files(&package.path, &package::files(&package)?)
+        .with_context(|| format!("failed to hash {}", package.path.display()))?;
+
+    Ok(Resolved {
+        requirement,
+        command,
+        _origin: origin,
+        package,
+        source,
+        hash,
+    })
+}
+
+impl Resolved<'_> {
+    /// What the package resolved to, with local paths relative to `root`.
+    fn locked(&self, root: &Path) -> Locked {
+        Locked::new(
+            &self.requirement.namespace,
+            &self.package.name,
+            &self.package.version,
+            self.hash.clone(),
+            self.source.clone(),
+            root,
+        )
+    }
+
+    /// Installs the package, unless it is installed with the same content
+    /// already.
+    ///
+    /// Either way, the source it was synced from is recorded in its metadata.
+    fn install(self, transaction: &mut Transaction) -> anyhow::Result<install::Outcome> {
+        let namespace = &self.requirement.namespace;
+        let (name, version) = (self.package.name.clone(), self.package.version.clone());
+        let source = self.source;
+        let outcome =
+            install::install(self.package, source.clone(), &self.command, transaction)?;
+
+        // The content is the same, but it may have been installed from elsewhere
+        // or without recording the hashes of its files.
+        let recorded = metadata::read(namespace, &name, &version);
+        if outcome == install::Outcome::Unchanged
+            && recorded.is_none_or(|m| m.source != source || m.files.is_empty())
+        {
+            let dir = package::local_dir()?.join(format!("{namespace}/{name}/{version}"));
+            metadata::write(namespace, &name, &version, &Metadata::new(source, &dir)?)?;
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// The packages that the `resolved` lockfile locks differently than `locked`,
+/// or that only one of them locks.
+fn outdated(locked: &Lockfile, resolved: &Lockfile) -> Vec<String> {
+    let mut outdated: Vec<_> = resolved
+        .packages
+        .iter()
+        .filter(|package| locked.get(&package.namespace, &package.name) != Some(package))
+        .chain(
+            locked.packages.iter().filter(|package| {
+                resolved.get(&package.namespace, &package.name).is_none()
+            }),
+        )
+        .map(|package| format!("@{}/{}", package.namespace, package.name))
+        .collect();
+
+    outdated.sort();
+    outdated
+}
+
+/// Picks the one package `requirement` asks for out of the `packages` found in
+/// its source.
+///
+/// # Errors
+///
+/// When no package matches, or several versions do and the requirement does
+/// not pin one.
+fn select(requirement: &Requirement, packages: Vec<Package>) -> anyhow::Result<Package> {
+    let mut matching: Vec<_> = packages
+        .into_iter()
+        .filter(|package| package.name == requirement.name)
+        .filter(|package| {
+            requirement.version.as_ref().is_none_or(|v| *v == package.version)
+        })
+        .collect();
+
+    match matching.len() {
+        1 => Ok(matching.remove(0)),
+        0 => {
+            let name = match &requirement.version {
+                Some(version) => format!("{}:{version}", requirement.name),
+                None => requirement.name.clone(),
+            };
+            bail!(Error::NotFound(format!("{name} not found in {}", requirement.source)))
+        }
+        _ => bail!(Error::Conflict(format!(
+            "{} holds several versions of {}, pick one with `version`",
+            requirement.source, requirement.name
+        ))),
+    }
+}
+
+/// Moves every package version in `namespaces` that is not `synced` to the
+/// trash, along with its metadata.
+fn prune(
+    namespaces: &BTreeSet<&str>,
+    synced: &BTreeSet<(String, String, Version)>,
+) -> anyhow::Result<()> {
+    let root_dir = package::local_dir()?;
+    trash::expire()?;
+
+    for namespace in namespaces {
+        for package in package::search(&root_dir.join(namespace)) {
+            let key =
+                (namespace.to_string(), package.name.clone(), package.version.clone());
+            if synced.contains(&key) {
+                continue;
+            }
+
+            print_pruning(namespace, &package).unwrap();
+            let installed = Installed {
+                name: package.name,
+                version: package.version,
+                path: package.path,
+            };
+            trash::add(namespace, &installed)
+                .with_context(|| format!("failed to prune @{namespace}/{installed}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print that a project manifest is being synced.
+fn print_syncing(manifest: &Path) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_help)?;
+    write!(w, "syncing")?;
+
+    w.reset()?;
+    writeln!(w, " {}", manifest.display())
+}
+
+/// Print that a package version is being pruned.
+fn print_pruning(namespace: &str, package: &Package) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_help)?;
+    write!(w, "pruning")?;
+
+    w.reset()?;
+    writeln!(w, " @{namespace}/{package}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testing, trash};
+
+    use super::*;
+
+    fn requirement(name: &str, version: Option<&str>) -> Requirement {
+        Requirement {
+            name: name.into(),
+            version: version.map(|v| v.parse().unwrap()),
+            namespace: "local".into(),
+            source: project::Spec::Path { path: "packages".into() },
+        }
+    }
+
+    #[test]
+    fn selects_the_required_package() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, version) in
+            [("mypkg", "0.1.0"), ("mypkg", "0.2.0"), ("other", "1.0.0")]
+        {
+            let path = dir.path().join(format!("{name}-{version}"));
+            testing::write_package(&path, name, version, "");
+        }
+        let packages = || package::search(&dir.path());
+
+        let selected = select(&requirement("other", None), packages()).unwrap();
+        assert_eq!(selected.to_string(), "other:1.0.0");
+        let selected = select(&requirement("mypkg", Some("0.2.0")), packages()).unwrap();
+        assert_eq!(selected.to_string(), "mypkg:0.2.0");
+
+        let err = select(&requirement("mypkg", None), packages()).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::Conflict(_))), "{err:#}");
+        for requirement in
+            [requirement("missing", None), requirement("other", Some("2.0.0"))]
+        {
+            let err = select(&requirement, packages()).unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(Error::NotFound(_))), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn prunes_into_the_trash() {
+        let data = testing::data_dir();
+        for (namespace, name) in
+            [("local", "kept"), ("local", "pruned"), ("acme", "other")]
+        {
+            let path = data.packages().join(format!("{namespace}/{name}/0.1.0"));
+            testing::write_package(&path, name, "0.1.0", "");
+        }
+
+        let synced =
+            BTreeSet::from([("local".into(), "kept".into(), Version::new(0, 1, 0))]);
+        prune(&BTreeSet::from(["local"]), &synced).unwrap();
+
+        assert!(data.packages().join("local/kept/0.1.0").is_dir());
+        assert!(!data.packages().join("local/pruned").exists());
+        assert!(data.packages().join("acme/other/0.1.0").is_dir());
+
+        let trashed = trash::entries().unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(
+            (trashed[0].namespace.as_str(), trashed[0].name.as_str()),
+            ("local", "pruned")
+        );
+    }
+
+    #[test]
+    fn reports_outdated_packages() {
+        let locked = |name: &str, hash: &str| {
+            let source = Source::Path { path: format!("pkgs/{name}").into() };
+            Locked::new(
+                "local",
+                name,
+                &Version::new(1, 0, 0),
+                hash.into(),
+                source,
+                "/".as_ref(),
+            )
+        };
+
+        let previous = Lockfile::new(vec![
+            locked("kept", "a"),
+            locked("changed", "a"),
+            locked("removed", "a"),
+        ]);
+        let resolved = Lockfile::new(vec![
+            locked("kept", "a"),
+            locked("changed", "b"),
+            locked("added", "a"),
+        ]);
+
+        assert_eq!(
+            outdated(&previous, &resolved),
+            ["@local/added", "@local/changed", "@local/removed"]
+        );
+        assert!(outdated(&previous, &previous).is_empty());
+        assert!(
+            outdated(&Lockfile::new(Vec::new()), &Lockfile::new(Vec::new())).is_empty()
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Helpers shared by the tests of several modules.
+
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use tempfile::TempDir;
+use url::Url;
+
+thread_local! {
+    /// The data directory of the test running on this thread, if it set one.
+    static DATA_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// The data directory the test running on this thread uses instead of the
+/// user's, see [`data_dir`].
+pub fn overridden_data_dir() -> Option<PathBuf> {
+    DATA_DIR.with(|dir| dir.borrow().clone())
+}
+
+/// A temporary data directory that the test running on this thread installs
+/// into, trashes into and reads metadata from until it is dropped.
+///
+/// Tests run in parallel on their own threads, so unlike `XDG_DATA_HOME` this
+/// does not leak into other tests.
+pub struct DataDir(TempDir);
+
+impl DataDir {
+    /// The temporary data directory.
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+
+    /// The local package directory in the temporary data directory.
+    pub fn packages(&self) -> PathBuf {
+        self.path().join("typst/packages")
+    }
+}
+
+impl Drop for DataDir {
+    fn drop(&mut self) {
+        DATA_DIR.with(|dir| dir.borrow_mut().take());
+    }
+}
+
+/// Uses a new temporary data directory for the test running on this thread.
+pub fn data_dir() -> DataDir {
+    let dir = tempfile::tempdir().unwrap();
+    DATA_DIR.with(|data_dir| *data_dir.borrow_mut() = Some(dir.path().to_owned()));
+    DataDir(dir)
+}
+
+/// Writes a package with a manifest and an entrypoint holding `source` to
+/// `dir`.
+pub fn write_package(dir: &Path, name: &str, version: &str, source: &str) {
+    fs::create_dir_all(dir).unwrap();
+    fs::write(
+        dir.join("typst.toml"),
+        format!(
+            "[package]\nname = \"{name}\"\nversion = \"{version}\"\n\
+             entrypoint = \"lib.typ\"\n"
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join("lib.typ"), source).unwrap();
+}
+
+/// A git repository with the package `mypkg` at 0.1.0 (tagged `v0.1.0`) and
+/// 0.2.0 on `main`, and at 0.3.0 plus the package `nested` in `sub` on the
+/// branch `next`, while `main` is checked out.
+pub struct Repo {
+    pub dir: TempDir,
+    pub url: Url,
+    /// The commits of 0.1.0, 0.2.0 and 0.3.0.
+    pub commits: [String; 3],
+}
+
+/// Creates the [`Repo`] in a temporary directory.
+pub fn repo() -> Repo {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("repo");
+    fs::create_dir(&path).unwrap();
+    git(&path, &["init", "--quiet", "--initial-branch", "main"]);
+
+    let first = commit(&path, "0.1.0");
+    git(&path, &["tag", "v0.1.0"]);
+    let second = commit(&path, "0.2.0");
+
+    git(&path, &["checkout", "--quiet", "-b", "next"]);
+    write_package(&path.join("sub"), "nested", "1.0.0", "");
+    let third = commit(&path, "0.3.0");
+    git(&path, &["checkout", "--quiet", "main"]);
+
+    let url = Url::from_file_path(&path).unwrap();
+    Repo { dir, url, commits: [first, second, third] }
+}
+
+/// Runs git with `args` in `repo` and returns what it printed.
+fn git(repo: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["-c", "user.name=typven", "-c", "user.email=typven@localhost"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).trim().to_owned()
+}
+
+/// Commits `mypkg` at `version` to `repo` and returns the commit.
+fn commit(repo: &Path, version: &str) -> String {
+    let source = format!("#let version = \"{version}\"\n");
+    write_package(repo, "mypkg", version, &source);
+    git(repo, &["add", "--all"]);
+    git(repo, &["commit", "--quiet", "-m", version]);
+    git(repo, &["rev-parse", "HEAD"])
+}
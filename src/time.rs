@@ -0,0 +1,228 @@
+//! Timestamps as TOML datetimes in UTC, such as `2024-01-31T12:00:00Z`, and
+//! durations such as `30d`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use toml::value::{Date, Datetime, Offset, Time};
+
+/// The current time, to the second.
+pub fn now() -> Datetime {
+    from_unix(unix(SystemTime::now()))
+}
+
+/// The seconds since the Unix epoch at `time`.
+pub fn unix(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+/// The seconds since the Unix epoch `age` ago, which is never later than now.
+pub fn ago(age: Duration) -> i64 {
+    let age = i64::try_from(age.as_secs()).unwrap_or(i64::MAX);
+    unix(SystemTime::now()).saturating_sub(age)
+}
+
+/// The seconds since the Unix epoch at `datetime`, or `None` if it is only a
+/// time of day.
+///
+/// Datetimes without an offset are taken to be in UTC.
+pub fn to_unix(datetime: &Datetime) -> Option<i64> {
+    let date = datetime.date?;
+    let mut secs = days(i64::from(date.year), date.month, date.day) * 86_400;
+
+    if let Some(time) = datetime.time {
+        secs += i64::from(time.hour) * 3600
+            + i64::from(time.minute) * 60
+            + i64::from(time.second);
+    }
+
+    if let Some(Offset::Custom { minutes }) = datetime.offset {
+        secs -= i64::from(minutes) * 60;
+    }
+
+    Some(secs)
+}
+
+/// The datetime `secs` seconds after the Unix epoch.
+pub fn from_unix(secs: i64) -> Datetime {
+    let (days, secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil(days);
+
+    Datetime {
+        date: Some(Date { year: year as u16, month, day }),
+        time: Some(Time {
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+            nanosecond: 0,
+        }),
+        offset: Some(Offset::Z),
+    }
+}
+
+/// The days since the Unix epoch at the day `day` of `month` in `year`.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days(year: i64, month: u8, day: u8) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5
+        + i64::from(day)
+        - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+/// The year, month and day of the day `days` days after the Unix epoch.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Parses a duration written as a number and a unit, such as `30d`: `s` for
+/// seconds, `m` for minutes, `h` for hours, `d` for days or `w` for weeks.
+///
+/// Durations longer than `i64::MAX` seconds are rejected, so every duration can
+/// be subtracted from a Unix timestamp.
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid =
+        || format!("`{duration}` is not a duration such as `12h`, `30d` or `2w`");
+
+    let split = duration.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = duration.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        _ => return Err(invalid()),
+    };
+
+    let secs = amount
+        .checked_mul(unit)
+        .filter(|&secs| i64::try_from(secs).is_ok())
+        .ok_or_else(|| format!("`{duration}` is too long a duration"))?;
+
+    Ok(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Datetimes around leap days and month and year boundaries, with the
+    /// seconds since the Unix epoch at each.
+    const DATETIMES: &[(&str, i64)] = &[
+        ("1970-01-01T00:00:00Z", 0),
+        ("1969-12-31T23:59:59Z", -1),
+        ("1900-02-28T00:00:00Z", -2_203_977_600),
+        ("1900-03-01T00:00:00Z", -2_203_891_200),
+        ("2000-02-29T00:00:00Z", 951_782_400),
+        ("2000-03-01T00:00:00Z", 951_868_800),
+        ("2023-02-28T00:00:00Z", 1_677_542_400),
+        ("2023-03-01T00:00:00Z", 1_677_628_800),
+        ("2023-12-31T23:59:59Z", 1_704_067_199),
+        ("2024-01-31T00:00:00Z", 1_706_659_200),
+        ("2024-02-01T00:00:00Z", 1_706_745_600),
+        ("2024-02-29T12:30:45Z", 1_709_209_845),
+        ("2100-03-01T00:00:00Z", 4_107_542_400),
+    ];
+
+    #[test]
+    fn converts_to_unix() {
+        for &(datetime, secs) in DATETIMES {
+            let parsed: Datetime = datetime.parse().unwrap();
+            assert_eq!(to_unix(&parsed), Some(secs), "{datetime}");
+        }
+    }
+
+    #[test]
+    fn converts_from_unix() {
+        for &(datetime, secs) in DATETIMES {
+            assert_eq!(from_unix(secs).to_string(), datetime, "{secs}");
+        }
+    }
+
+    #[test]
+    fn converts_offsets_and_dates() {
+        let parse = |datetime: &str| to_unix(&datetime.parse().unwrap());
+
+        assert_eq!(parse("2024-01-01T01:00:00+01:00"), Some(1_704_067_200));
+        assert_eq!(parse("2023-12-31T19:00:00-05:00"), Some(1_704_067_200));
+        assert_eq!(parse("2024-01-01T00:00:00"), Some(1_704_067_200));
+        assert_eq!(parse("2024-01-01"), Some(1_704_067_200));
+        assert_eq!(parse("12:00:00"), None);
+    }
+
+    #[test]
+    fn parses_durations() {
+        let secs = |duration: &str| parse_duration(duration).unwrap().as_secs();
+
+        assert_eq!(secs("0s"), 0);
+        assert_eq!(secs("45s"), 45);
+        assert_eq!(secs("90m"), 5400);
+        assert_eq!(secs("12h"), 43_200);
+        assert_eq!(secs("30d"), 2_592_000);
+        assert_eq!(secs("2w"), 1_209_600);
+        assert_eq!(secs("9223372036854775807s"), i64::MAX as u64);
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        for duration in
+            ["9223372036854775808s", "15250284452472w", "18446744073709551615w"]
+        {
+            assert!(parse_duration(duration).is_err(), "{duration:?}");
+        }
+    }
+
+    #[test]
+    fn subtracts_ages_from_now() {
+        let now = unix(SystemTime::now());
+
+        assert!((now..=now + 1).contains(&ago(Duration::ZERO)));
+        assert!((now - 3600..=now - 3599).contains(&ago(Duration::from_secs(3600))));
+        assert!(ago(Duration::from_secs(i64::MAX as u64)) < 0);
+        assert!(ago(Duration::MAX) < 0);
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for duration in [
+            "",
+            "d",
+            "30",
+            "30x",
+            "30D",
+            "-1d",
+            "+1d",
+            "1.5d",
+            " 30d",
+            "30d ",
+            "30 d",
+            "30dd",
+            "1d2h",
+            "99999999999999999999d",
+        ] {
+            assert!(parse_duration(duration).is_err(), "{duration:?}");
+        }
+    }
+}
@@ -0,0 +1,522 @@
+//! The trash that `clean` moves package versions into, so a mistaken clean can
+//! be undone with `typven restore`.
+//!
+//! Every trashed package version is an entry in
+//! `{data-dir}/typven/trash/{id}`, holding the package directory as `package`,
+//! its [metadata](crate::metadata) as `metadata.toml` if it had any, and what
+//! was trashed when in `entry.toml`. Entries expire after
+//! [`TYPVEN_TRASH_EXPIRY`](EXPIRY_VAR), 30 days unless set otherwise, and are
+//! removed by the next command that uses the trash.
+
+use std::{
+    cmp::Reverse,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use codespan_reporting::term::{self, termcolor::WriteColor};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use toml::value::Datetime;
+
+use crate::{
+    cli::{EmptyTrashCommand, RestoreCommand, TrashAction, TrashCommand},
+    color_stream,
+    error::Error,
+    metadata::{self, Metadata},
+    package::{self, Installed},
+    time, util,
+};
+
+/// The environment variable that sets how long entries are kept in the trash,
+/// as a duration such as `30d`, or `never` to keep them until the trash is
+/// emptied.
+pub const EXPIRY_VAR: &str = "TYPVEN_TRASH_EXPIRY";
+
+/// How long entries are kept in the trash unless [`EXPIRY_VAR`] is set.
+const DEFAULT_EXPIRY: Duration = Duration::from_secs(30 * 86_400);
+
+/// The file in an entry that records what was trashed.
+const ENTRY_FILE: &str = "entry.toml";
+
+/// The file in an entry holding the metadata of the trashed package.
+const METADATA_FILE: &str = "metadata.toml";
+
+/// The directory in an entry holding the trashed package.
+const PACKAGE_DIR: &str = "package";
+
+/// A package version in the trash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub namespace: String,
+    pub name: String,
+    pub version: Version,
+    /// When the package version was moved to the trash.
+    pub trashed: Datetime,
+    /// The directory of the entry.
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Entry {
+    /// When the package version was moved to the trash, in seconds since the
+    /// Unix epoch.
+    fn trashed_at(&self) -> i64 {
+        time::to_unix(&self.trashed).unwrap_or(0)
+    }
+}
+
+/// The trash directory: `{data-dir}/typven/trash`.
+pub fn dir() -> anyhow::Result<PathBuf> {
+    Ok(package::data_dir()?.join("typven/trash"))
+}
+
+/// Moves the installed `package` in `namespace` and its metadata to the trash.
+///
+/// # Errors
+///
+/// When the trash can not be written to or the package can not be moved.
+pub fn add(namespace: &str, package: &Installed) -> anyhow::Result<()> {
+    let now = time::unix(SystemTime::now());
+    let id = format!("{now}-{namespace}-{}-{}", package.name, package.version);
+    let (id, path) = unique(&dir()?, &id)?;
+
+    let entry = Entry {
+        namespace: namespace.to_owned(),
+        name: package.name.clone(),
+        version: package.version.clone(),
+        trashed: time::from_unix(now),
+        path: path.clone(),
+    };
+    let contents = toml::to_string(&entry).context("failed to serialize trash entry")?;
+    fs::write(path.join(ENTRY_FILE), contents)
+        .with_context(|| format!("failed to write trash entry {id}"))?;
+
+    if let Err(err) = package::move_dir(&package.path, &path.join(PACKAGE_DIR)) {
+        // A package that was copied but not fully removed stays in the trash.
+        if path.join(PACKAGE_DIR).symlink_metadata().is_err() {
+            fs::remove_dir_all(&path).unwrap_or(());
+        }
+        bail!(Error::Io(format!(
+            "failed to move {} to the trash: {err}",
+            package.path.display()
+        )));
+    }
+
+    if let Some(metadata) = metadata::read(namespace, &package.name, &package.version) {
+        let contents =
+            toml::to_string(&metadata).context("failed to serialize metadata")?;
+        fs::write(path.join(METADATA_FILE), contents)
+            .with_context(|| format!("failed to write trash entry {id}"))?;
+    }
+    metadata::remove(namespace, &package.name, Some(&package.version))?;
+
+    // Only succeeds when no other versions are left in the bundle.
+    fs::remove_dir(package.path.parent().unwrap()).unwrap_or(());
+
+    Ok(())
+}
+
+/// Creates a new entry directory in `trash`, named `id` or `id-2`, `id-3` and
+/// so on if it is taken already.
+fn unique(trash: &Path, id: &str) -> anyhow::Result<(String, PathBuf)> {
+    fs::create_dir_all(trash)
+        .with_context(|| format!("failed to create {}", trash.display()))?;
+
+    for n in 1.. {
+        let id = match n {
+            1 => id.to_owned(),
+            n => format!("{id}-{n}"),
+        };
+
+        let path = trash.join(&id);
+        match fs::create_dir(&path) {
+            Ok(()) => return Ok((id, path)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                bail!(Error::Io(format!("failed to create {}: {err}", path.display())))
+            }
+        }
+    }
+
+    unreachable!()
+}
+
+/// Lists every entry in the trash, the most recently trashed first.
+///
+/// Entries that can not be read are left out.
+pub fn entries() -> anyhow::Result<Vec<Entry>> {
+    Ok(read()?.0)
+}
+
+/// Lists every entry in the trash, the most recently trashed first, and the
+/// paths in the trash that are not an entry that can be read.
+fn read() -> anyhow::Result<(Vec<Entry>, Vec<PathBuf>)> {
+    let dir = dir()?;
+    let read_dir = match fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok((Vec::new(), Vec::new()))
+        }
+        Err(err) => bail!(Error::Io(format!("failed to read {}: {err}", dir.display()))),
+    };
+
+    let mut entries = Vec::new();
+    let mut unreadable = Vec::new();
+    for e in read_dir {
+        let path = e.with_context(|| format!("failed to read {}", dir.display()))?.path();
+        match fs::read_to_string(path.join(ENTRY_FILE))
+            .ok()
+            .and_then(|contents| toml::from_str::<Entry>(&contents).ok())
+        {
+            Some(entry) => entries.push(Entry { path, ..entry }),
+            None => unreadable.push(path),
+        }
+    }
+
+    entries.sort_by(|a, b| {
+        (Reverse(a.trashed_at()), &a.namespace, &a.name, &a.version).cmp(&(
+            Reverse(b.trashed_at()),
+            &b.namespace,
+            &b.name,
+            &b.version,
+        ))
+    });
+    unreadable.sort();
+
+    Ok((entries, unreadable))
+}
+
+/// Removes the `entry` from the trash for good.
+pub fn remove(entry: &Entry) -> io::Result<()> {
+    // Does not follow the symlink of a trashed linked package.
+    fs::remove_dir_all(&entry.path)
+}
+
+/// How long entries are kept in the trash, or `None` to keep them forever.
+///
+/// # Errors
+///
+/// When [`EXPIRY_VAR`] is not a duration.
+pub fn expiry() -> anyhow::Result<Option<Duration>> {
+    match env::var(EXPIRY_VAR) {
+        Ok(expiry) if expiry == "never" => Ok(None),
+        Ok(expiry) => match time::parse_duration(&expiry) {
+            Ok(expiry) => Ok(Some(expiry)),
+            Err(err) => bail!("{EXPIRY_VAR} is invalid: {err}, or `never`"),
+        },
+        Err(_) => Ok(Some(DEFAULT_EXPIRY)),
+    }
+}
+
+/// Removes the entries trashed longer ago than the [`expiry`], returning how
+/// many were removed.
+pub fn expire() -> anyhow::Result<usize> {
+    let Some(expiry) = expiry()? else {
+        return Ok(0);
+    };
+
+    empty_older_than(expiry)
+}
+
+/// Removes the entries trashed longer ago than `age`, returning how many were
+/// removed.
+fn empty_older_than(age: Duration) -> anyhow::Result<usize> {
+    let cutoff = time::ago(age);
+
+    let mut removed = 0;
+    for entry in entries()? {
+        if entry.trashed_at() <= cutoff {
+            remove(&entry).with_context(|| {
+                format!("failed to remove {} from the trash", entry.path.display())
+            })?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Restores package versions from the trash.
+///
+/// Restores the most recently trashed entry of every package version that
+/// matches, along with its metadata. Without a package, everything in the
+/// selected namespaces is restored.
+///
+/// # Errors
+///
+/// When nothing in the trash matches, or a package version to restore is
+/// installed already.
+pub fn restore(command: RestoreCommand) -> anyhow::Result<()> {
+    expire()?;
+
+    let mut selected: Vec<Entry> = Vec::new();
+    for entry in entries()? {
+        let matches = (command.namespace.all_namespaces
+            || entry.namespace == command.namespace.namespace)
+            && command.package.as_ref().is_none_or(|arg| {
+                arg.name == entry.name
+                    && arg.version.as_ref().is_none_or(|v| *v == entry.version)
+            });
+
+        // Entries are sorted newest first, so the first one of a version wins.
+        let restored = selected.iter().any(|selected| {
+            (&selected.namespace, &selected.name, &selected.version)
+                == (&entry.namespace, &entry.name, &entry.version)
+        });
+
+        if matches && !restored {
+            selected.push(entry);
+        }
+    }
+
+    if selected.is_empty() {
+        bail!(Error::NotFound(match &command.package {
+            Some(arg) => match &arg.version {
+                Some(version) => format!("{}:{version} is not in the trash", arg.name),
+                None => format!("{} is not in the trash", arg.name),
+            },
+            None => "nothing to restore, the trash has no package versions of the \
+                     selected namespace(s)"
+                .into(),
+        }));
+    }
+
+    let root_dir = package::local_dir()?;
+    let dest = |entry: &Entry| {
+        root_dir.join(format!("{}/{}/{}", entry.namespace, entry.name, entry.version))
+    };
+
+    // Nothing is restored unless everything can be.
+    let installed: Vec<_> = selected
+        .iter()
+        .filter(|entry| dest(entry).symlink_metadata().is_ok())
+        .map(|entry| format!("@{}/{}:{}", entry.namespace, entry.name, entry.version))
+        .collect();
+    if !installed.is_empty() {
+        bail!(Error::Conflict(format!(
+            "{} installed already, clean it first to restore it from the trash",
+            installed.join(", ")
+        )));
+    }
+
+    for entry in &selected {
+        print_restoring(entry).unwrap();
+
+        let dest = dest(entry);
+        fs::create_dir_all(dest.parent().unwrap()).with_context(|| {
+            format!("failed to create {}", dest.parent().unwrap().display())
+        })?;
+        package::move_dir(&entry.path.join(PACKAGE_DIR), &dest).map_err(|err| {
+            Error::Io(format!("failed to restore {}: {err}", dest.display()))
+        })?;
+
+        if let Some(metadata) = fs::read_to_string(entry.path.join(METADATA_FILE))
+            .ok()
+            .and_then(|contents| toml::from_str::<Metadata>(&contents).ok())
+        {
+            metadata::write(&entry.namespace, &entry.name, &entry.version, &metadata)?;
+        }
+
+        remove(entry).with_context(|| {
+            format!("failed to remove {} from the trash", entry.path.display())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Lists or empties the trash.
+pub fn trash(command: TrashCommand) -> anyhow::Result<()> {
+    match command.action {
+        TrashAction::List => list(),
+        TrashAction::Empty(command) => empty(command),
+    }
+}
+
+/// Lists the entries in the trash, the most recently trashed first.
+fn list() -> anyhow::Result<()> {
+    expire()?;
+
+    let entries = entries()?;
+    if entries.is_empty() {
+        util::print_note("the trash is empty").unwrap();
+        return Ok(());
+    }
+
+    for entry in entries {
+        let package = entry.path.join(PACKAGE_DIR);
+        let contents = match package::link_target(&package) {
+            Some(target) => format!("link -> {}", target.display()),
+            None => util::format_size(package::size(&package).unwrap_or(0)),
+        };
+
+        println!(
+            "@{}/{}:{} (trashed {}, {contents})",
+            entry.namespace, entry.name, entry.version, entry.trashed
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes the entries in the trash for good, or only the ones trashed longer
+/// ago than `--older-than`.
+///
+/// # Errors
+///
+/// When an entry can not be removed, or there is something in the trash that
+/// is not an entry that can be read.
+fn empty(command: EmptyTrashCommand) -> anyhow::Result<()> {
+    let removed = empty_older_than(command.older_than.unwrap_or(Duration::ZERO))?;
+    util::print_note(&format!("removed {removed} package version(s) from the trash"))
+        .unwrap();
+
+    let (_, unreadable) = read()?;
+    if !unreadable.is_empty() {
+        let paths: Vec<_> =
+            unreadable.iter().map(|path| path.display().to_string()).collect();
+        bail!(Error::Io(format!(
+            "failed to read {} trash entries, remove them by hand if they are not \
+             needed:\n  {}",
+            paths.len(),
+            paths.join("\n  ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// Print that a package version is being restored.
+fn print_restoring(entry: &Entry) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_help)?;
+    write!(w, "restoring")?;
+
+    w.reset()?;
+    writeln!(
+        w,
+        " @{}/{}:{} (trashed {})",
+        entry.namespace, entry.name, entry.version, entry.trashed
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cli::{NamespaceArgs, PackageArg},
+        metadata::Source,
+        testing,
+    };
+
+    use super::*;
+
+    fn installed(dir: &Path) -> Installed {
+        Installed {
+            path: dir.to_owned(),
+            name: dir.parent().unwrap().file_name().unwrap().to_str().unwrap().into(),
+            version: dir.file_name().unwrap().to_str().unwrap().parse().unwrap(),
+        }
+    }
+
+    fn restore_command(package: &str) -> RestoreCommand {
+        RestoreCommand {
+            package: Some(PackageArg { name: package.into(), version: None }),
+            namespace: NamespaceArgs { namespace: "local".into(), all_namespaces: false },
+        }
+    }
+
+    #[test]
+    fn restores_trashed_versions() {
+        let data = testing::data_dir();
+        let version = "0.1.0".parse().unwrap();
+
+        let copied = data.packages().join("local/copied/0.1.0");
+        testing::write_package(&copied, "copied", "0.1.0", "#let x = 1\n");
+        let source = Source::Path { path: "/src/copied".into() };
+        let metadata = Metadata::new(source.clone(), &copied).unwrap();
+        metadata::write("local", "copied", &version, &metadata).unwrap();
+
+        let linked_source = data.path().join("src/linked");
+        testing::write_package(&linked_source, "linked", "0.1.0", "#let y = 2\n");
+        let linked = data.packages().join("local/linked/0.1.0");
+        fs::create_dir_all(linked.parent().unwrap()).unwrap();
+        package::symlink_dir(&linked_source, &linked).unwrap();
+
+        add("local", &installed(&copied)).unwrap();
+        add("local", &installed(&linked)).unwrap();
+
+        assert!(!copied.parent().unwrap().exists());
+        assert!(!linked.parent().unwrap().exists());
+        assert!(metadata::read("local", "copied", &version).is_none());
+        assert!(linked_source.join("lib.typ").is_file());
+        assert_eq!(entries().unwrap().len(), 2);
+
+        restore(restore_command("copied")).unwrap();
+        restore(restore_command("linked")).unwrap();
+
+        assert_eq!(fs::read_to_string(copied.join("lib.typ")).unwrap(), "#let x = 1\n");
+        assert_eq!(package::link_target(&linked), Some(linked_source));
+        let restored = metadata::read("local", "copied", &version).unwrap();
+        assert_eq!(restored.source, source);
+        assert_eq!(restored.files, metadata.files);
+        assert!(entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_to_restore_over_installed_versions() {
+        let data = testing::data_dir();
+        let dir = data.packages().join("local/mypkg/0.1.0");
+
+        testing::write_package(&dir, "mypkg", "0.1.0", "#let x = 1\n");
+        add("local", &installed(&dir)).unwrap();
+        testing::write_package(&dir, "mypkg", "0.1.0", "#let x = 2\n");
+
+        let err = restore(restore_command("mypkg")).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::Conflict(_))), "{err:#}");
+        assert_eq!(fs::read_to_string(dir.join("lib.typ")).unwrap(), "#let x = 2\n");
+        assert_eq!(entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn empties_entries_older_than() {
+        let data = testing::data_dir();
+
+        for name in ["old", "new"] {
+            let dir = data.packages().join(format!("local/{name}/0.1.0"));
+            testing::write_package(&dir, name, "0.1.0", "");
+            add("local", &installed(&dir)).unwrap();
+        }
+
+        // Backdate the entry of `old` by ten days.
+        let old = entries().unwrap().into_iter().find(|e| e.name == "old").unwrap();
+        let entry = Entry {
+            trashed: time::from_unix(old.trashed_at() - 864_000),
+            ..old
+        };
+        fs::write(entry.path.join(ENTRY_FILE), toml::to_string(&entry).unwrap()).unwrap();
+
+        let week = Duration::from_secs(7 * 86_400);
+        empty(EmptyTrashCommand { older_than: Some(week) }).unwrap();
+        let names: Vec<_> = entries().unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, ["new"]);
+
+        empty(EmptyTrashCommand { older_than: None }).unwrap();
+        assert!(entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn empty_reports_unreadable_entries() {
+        let _data = testing::data_dir();
+
+        fs::create_dir_all(dir().unwrap().join("stray")).unwrap();
+        let err = empty(EmptyTrashCommand { older_than: None }).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::Io(_))), "{err:#}");
+        assert!(err.to_string().contains("stray"), "{err:#}");
+    }
+}
@@ -11,7 +11,7 @@ use tempfile::NamedTempFile;
 use xz2::read::XzDecoder;
 use zip::ZipArchive;
 
-use crate::cli::UpdateCommand;
+use crate::{cli::UpdateCommand, error::Error};
 
 const TYPVEN_GITHUB_ORG: &str = "jimvdl";
 const TYPVEN_REPO: &str = "typven";
@@ -33,33 +33,36 @@ pub fn update(command: UpdateCommand) -> anyhow::Result<()> {
         }
 
         if !command.force && version < &current_tag {
-            bail!(
+            bail!(Error::Conflict(
                 "downgrading requires the --force flag: \
                 `typven update <VERSION> --force`"
-            );
+                    .into()
+            ));
         }
     }
 
     let backup_path = backup_path()?;
     if command.revert {
         if !backup_path.exists() {
-            bail!(
+            bail!(Error::NotFound(format!(
                 "unable to revert, no backup found (searched at {})",
                 backup_path.display()
-            );
+            )));
         }
 
         return self_replace::self_replace(&backup_path)
             .and_then(|_| fs::remove_file(&backup_path))
-            .map_err(|err| anyhow!("failed to revert to backup: {err}"));
+            .map_err(|err| {
+                Error::Io(format!("failed to revert to backup: {err}")).into()
+            });
     }
 
     let current_exe = env::current_exe().map_err(|err| {
-        anyhow!("failed to locate path of the running executable: {err}")
+        Error::Io(format!("failed to locate path of the running executable: {err}"))
     })?;
 
     fs::copy(current_exe, &backup_path)
-        .map_err(|err| anyhow!("failed to create backup: {err}"))?;
+        .map_err(|err| Error::Io(format!("failed to create backup: {err}")))?;
 
     let release = Release::from_tag(command.version)?;
     if !update_needed(&release)? && !command.force {
@@ -69,14 +72,14 @@ pub fn update(command: UpdateCommand) -> anyhow::Result<()> {
 
     let binary_data = release.download_binary(needed_asset()?)?;
     let mut temp_exe = NamedTempFile::new()
-        .map_err(|err| anyhow!("failed to create temporary file: {err}"))?;
+        .map_err(|err| Error::Io(format!("failed to create temporary file: {err}")))?;
     temp_exe
         .write_all(&binary_data)
-        .map_err(|err| anyhow!("failed to write binary data: {err}"))?;
+        .map_err(|err| Error::Io(format!("failed to write binary data: {err}")))?;
 
     self_replace::self_replace(&temp_exe).map_err(|err| {
         fs::remove_file(&temp_exe).ok();
-        anyhow!("failed to self-replace running executable: {err}")
+        Error::Io(format!("failed to self-replace running executable: {err}")).into()
     })
 }
 
@@ -112,13 +115,17 @@ impl Release {
         };
 
         match ureq::get(&url).call() {
-            Ok(response) => response
-                .into_json()
-                .map_err(|err| anyhow!("unable to parse JSON response: {err}")),
+            Ok(response) => response.into_json().map_err(|err| {
+                Error::Network(format!("unable to parse JSON response: {err}")).into()
+            }),
             Err(ureq::Error::Status(404, _)) => {
-                bail!("release not found (searched at {url})")
+                bail!(Error::NotFound(format!("release not found (searched at {url})")))
+            }
+            Err(_) => {
+                bail!(Error::Network(
+                    "failed to download release (network failed)".into()
+                ))
             }
-            Err(_) => bail!("failed to download release (network failed)"),
         }
     }
 
@@ -130,22 +137,28 @@ impl Release {
             .assets
             .iter()
             .find(|a| a.name.starts_with(asset_name))
-            .ok_or(anyhow!("could not find release for your target platform"))?;
+            .ok_or_else(|| {
+                Error::NotFound("could not find release for your target platform".into())
+            })?;
 
         eprintln!("Downloading release ...");
         let response = match ureq::get(&asset.browser_download_url).call() {
             Ok(response) => response,
             Err(ureq::Error::Status(404, _)) => {
-                bail!("asset not found (searched for {})", asset.name);
+                bail!(Error::NotFound(format!(
+                    "asset not found (searched for {})",
+                    asset.name
+                )));
+            }
+            Err(_) => {
+                bail!(Error::Network("failed to load asset (network failed)".into()))
             }
-            Err(_) => bail!("failed to load asset (network failed)"),
         };
 
         let mut data = Vec::new();
-        response
-            .into_reader()
-            .read_to_end(&mut data)
-            .map_err(|err| anyhow!("failed to read response buffer: {err}"))?;
+        response.into_reader().read_to_end(&mut data).map_err(|err| {
+            Error::Network(format!("failed to read response buffer: {err}"))
+        })?;
 
         if asset_name.contains("windows") {
             extract_binary_from_zip(&data, asset_name)
@@ -229,18 +242,18 @@ fn update_needed(release: &Release) -> anyhow::Result<bool> {
 ///  - `%APPDATA%` on Windows
 fn backup_path() -> anyhow::Result<PathBuf> {
     #[cfg(target_os = "linux")]
-    let root_backup_dir = dirs::state_dir()
-        .or_else(|| dirs::data_dir())
-        .ok_or(anyhow!("unable to locate local data or state directory"))?;
+    let root_backup_dir = dirs::state_dir().or_else(dirs::data_dir).ok_or_else(|| {
+        Error::NotFound("unable to locate local data or state directory".into())
+    })?;
 
     #[cfg(not(target_os = "linux"))]
-    let root_backup_dir =
-        dirs::data_dir().ok_or(anyhow!("unable to locate local data directory"))?;
+    let root_backup_dir = dirs::data_dir()
+        .ok_or_else(|| Error::NotFound("unable to locate local data directory".into()))?;
 
     let backup_dir = root_backup_dir.join("typven");
 
     fs::create_dir_all(&backup_dir)
-        .map_err(|err| anyhow!("failed to create backup directory: {err}"))?;
+        .map_err(|err| Error::Io(format!("failed to create backup directory: {err}")))?;
 
     Ok(backup_dir.join("typven_backup.part"))
 }
@@ -1,142 +1,952 @@
-//! Several utility functions, such as `ls` and `clean`.
+//! Several utility functions, such as `ls`, `info` and `clean`.
 
 use std::{
-    collections::HashMap,
-    fs,
-    io::{self, Write},
+    cmp::Reverse,
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
 };
 
 use anyhow::{bail, Context};
+use clap::error::ErrorKind;
 use codespan_reporting::term::{self, termcolor::WriteColor};
 use comfy_table::{modifiers::UTF8_ROUND_CORNERS, Table};
-use walkdir::WalkDir;
+use semver::Version;
+use serde::Serialize;
 
-use crate::{cli::CleanCommand, color_stream, package};
+use crate::{
+    cli::{
+        self, CleanCommand, InfoCommand, LsCommand, NamespaceArgs, OutputFormat, SortKey,
+    },
+    color_stream,
+    error::Error,
+    filter::Filter,
+    metadata::{self, Mode, Source},
+    package::{self, Installed, Package, PackageManifest},
+    time, trash,
+};
 
-/// Lists the locally installed packages in table format.
+/// Lists the locally installed packages.
 ///
-/// If a package is not valid, i.e. does not contain a valid `typst.toml`, `ls`
-/// will silently ignore that directory.
+/// Lists a single namespace, or every namespace, optionally only the packages
+/// that match a [filter](crate::filter) or only the newest version of each
+/// package. Versions are listed newest first, packages are sorted by name
+/// unless told otherwise.
 ///
-/// # Errors
+/// The table shows every version with where and when it was installed from,
+/// such as the commit of packages installed from git or the target of linked
+/// packages, and has an additional namespace column for every namespace. JSON
+/// and TOML hold the manifest and install metadata of every version, plain text
+/// lists one import path per line. If a package is not valid, i.e. does not
+/// contain a valid `typst.toml`, `ls` will silently ignore that directory.
 ///
-/// No packages are installed.
-pub fn ls() -> anyhow::Result<()> {
-    let packages_dir = dirs::data_dir()
-        .context("failed to locate data directory")?
-        .join("typst/packages/local");
+/// No packages being installed is not an error, the list is just empty.
+pub fn ls(command: LsCommand) -> anyhow::Result<()> {
+    let packages = list(&command)?;
+    if packages.is_empty() && command.format == OutputFormat::Table {
+        print_note("no packages installed").unwrap();
+        return Ok(());
+    }
 
-    let packages = package::search(&packages_dir);
+    let all_namespaces = command.namespace.all_namespaces;
+    print!("{}", render(&packages, command.format, all_namespaces)?);
+    Ok(())
+}
 
-    if packages.is_empty() {
-        bail!("no valid packages found");
+/// The installed packages that `command` lists, in the order it lists them.
+fn list(command: &LsCommand) -> anyhow::Result<Vec<Listing>> {
+    let root_dir = package::local_dir()?;
+
+    let mut map: BTreeMap<(String, String), Vec<Listed>> = BTreeMap::new();
+    for namespace in target_namespaces(&command.namespace)? {
+        for package in package::search(&root_dir.join(&namespace)) {
+            if let Some(filter) = &command.filter {
+                if !filter.matches(&package.name, &package.version) {
+                    continue;
+                }
+            }
+
+            let metadata = metadata::read(&namespace, &package.name, &package.version);
+            let listed = Listed {
+                version: package.version,
+                link: package::link_target(&package.path),
+                path: package.path,
+                manifest: package.manifest,
+                installed: metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.installed.as_ref())
+                    .map(ToString::to_string),
+                source: metadata.map(|metadata| metadata.source),
+            };
+
+            map.entry((namespace.clone(), package.name)).or_default().push(listed);
+        }
     }
 
-    let mut map: HashMap<String, String> = HashMap::new();
-    for package in packages {
-        map.entry(package.name)
-            .and_modify(|e| {
-                let mut v = package.version.to_string();
-                v.insert(0, '\n');
-                e.push_str(&v);
-            })
-            .or_insert(package.version.to_string());
+    let mut packages: Vec<Listing> = map
+        .into_iter()
+        .map(|((namespace, name), mut versions)| {
+            versions.sort_by(|a, b| b.version.cmp(&a.version));
+            if command.latest {
+                versions.truncate(1);
+            }
+
+            Listing { namespace, name, versions }
+        })
+        .collect();
+
+    // Sorts are stable, so packages that tie stay sorted by name.
+    match command.sort {
+        SortKey::Name => {}
+        SortKey::Versions => {
+            packages.sort_by_key(|package| Reverse(package.versions.len()));
+        }
+        SortKey::Size => packages.sort_by_cached_key(|package| {
+            let size: u64 = package
+                .versions
+                .iter()
+                .map(|listed| package::size(&listed.path).unwrap_or(0))
+                .sum();
+            Reverse(size)
+        }),
+        // Install times are all in UTC, so they sort as strings.
+        SortKey::Installed => packages.sort_by_cached_key(|package| {
+            Reverse(package.versions.iter().filter_map(|l| l.installed.clone()).max())
+        }),
     }
 
-    let mut packages: Vec<(String, String)> = map.into_iter().collect();
-    packages.sort();
+    Ok(packages)
+}
+
+/// Renders the `packages` in `format`, with their namespaces in the table if
+/// `namespaces`.
+fn render(
+    packages: &[Listing],
+    format: OutputFormat,
+    namespaces: bool,
+) -> anyhow::Result<String> {
+    Ok(match format {
+        OutputFormat::Table => format!("{}\n", table(packages, namespaces)),
+        OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(packages)?),
+        OutputFormat::Toml => {
+            #[derive(Serialize)]
+            struct Document<'a> {
+                package: &'a [Listing],
+            }
+
+            toml::to_string(&Document { package: packages })?
+        }
+        OutputFormat::Plain => packages
+            .iter()
+            .flat_map(|package| {
+                package.versions.iter().map(|listed| {
+                    format!(
+                        "@{}/{}:{}\n",
+                        package.namespace, package.name, listed.version
+                    )
+                })
+            })
+            .collect(),
+    })
+}
+
+/// An installed package with every installed version of it.
+#[derive(Debug, Serialize)]
+struct Listing {
+    namespace: String,
+    name: String,
+    versions: Vec<Listed>,
+}
+
+/// An installed version of a package.
+#[derive(Debug, Serialize)]
+struct Listed {
+    version: Version,
+    path: PathBuf,
+    /// What the package links to, if it is linked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link: Option<PathBuf>,
+    manifest: PackageManifest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<Source>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    installed: Option<String>,
+}
+
+impl Listed {
+    /// A short description of where the version was installed from, such as
+    /// `git 1cf801d` or `link -> ~/my-packages/mypkg`.
+    fn source(&self) -> String {
+        if let Some(target) = &self.link {
+            return format!("link -> {}", target.display());
+        }
 
+        match &self.source {
+            Some(source) => match source.revision() {
+                Some(revision) => format!("{} {revision}", source.kind()),
+                None => source.kind().to_owned(),
+            },
+            None => "-".into(),
+        }
+    }
+}
+
+/// The `packages` as a table, with a namespace column if `namespaces`.
+fn table(packages: &[Listing], namespaces: bool) -> Table {
     let mut table = Table::new();
+    table.apply_modifier(UTF8_ROUND_CORNERS);
+
+    let mut header = vec!["package", "versions", "source", "installed"];
+    if namespaces {
+        header.insert(0, "namespace");
+    }
+    table.set_header(header);
+
+    for package in packages {
+        let column = |cell: fn(&Listed) -> String| {
+            package.versions.iter().map(cell).collect::<Vec<_>>().join("\n")
+        };
+
+        let mut row = vec![
+            package.name.clone(),
+            column(|listed| listed.version.to_string()),
+            column(Listed::source),
+            column(|listed| {
+                // Only the date, the time is in the JSON and TOML output.
+                let installed = listed.installed.as_deref().unwrap_or("-");
+                installed.split('T').next().unwrap().to_owned()
+            }),
+        ];
+        if namespaces {
+            row.insert(0, package.namespace.clone());
+        }
+        table.add_row(row);
+    }
+
     table
-        .set_header(vec!["package", "versions"])
-        .apply_modifier(UTF8_ROUND_CORNERS);
+}
 
-    packages.into_iter().for_each(|(k, v)| {
-        table.add_row(vec![k, v]);
-    });
+/// Shows the manifest, size and install metadata of every installed version of
+/// a package.
+///
+/// # Errors
+///
+/// The package, or the given version of it, is not installed.
+pub fn info(command: InfoCommand) -> anyhow::Result<()> {
+    let root_dir = package::local_dir()?;
+    let arg = &command.package;
 
-    println!("{table}");
+    let mut packages = Vec::new();
+    for namespace in target_namespaces(&command.namespace)? {
+        for package in package::search(&root_dir.join(&namespace)) {
+            if package.name == arg.name
+                && arg.version.as_ref().is_none_or(|v| *v == package.version)
+            {
+                packages.push((namespace.clone(), package));
+            }
+        }
+    }
+
+    if packages.is_empty() {
+        bail!(Error::NotFound(match &arg.version {
+            Some(version) => format!("{}:{version} is not installed", arg.name),
+            None => format!("{} is not installed", arg.name),
+        }));
+    }
+
+    packages.sort_by(|(a, p), (b, q)| (a, &p.version).cmp(&(b, &q.version)));
+
+    for (i, (namespace, package)) in packages.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+
+        print_info(namespace, package)?;
+    }
 
     Ok(())
 }
 
+/// Prints the manifest, size and install metadata of an installed `package`.
+fn print_info(namespace: &str, package: &Package) -> anyhow::Result<()> {
+    let spec = &package.manifest.package;
+    let metadata = metadata::read(namespace, &package.name, &package.version);
+    let size = package::size(&package.path)
+        .with_context(|| format!("failed to read {}", package.path.display()))?;
+
+    let mut fields: Vec<(&str, String)> = vec![];
+    let mut field = |key, value: Option<String>| {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            fields.push((key, value));
+        }
+    };
+
+    field("description", spec.description.clone());
+    field("authors", Some(spec.authors.join(", ")));
+    field("license", spec.license.clone());
+    field("entrypoint", Some(spec.entrypoint.display().to_string()));
+    field("compiler", spec.compiler.clone());
+    field("repository", spec.repository.clone());
+    field("homepage", spec.homepage.clone());
+    field("keywords", Some(spec.keywords.join(", ")));
+    field("categories", Some(spec.categories.join(", ")));
+    field("disciplines", Some(spec.disciplines.join(", ")));
+    field(
+        "template",
+        package.manifest.template.as_ref().map(|template| {
+            format!("{} ({})", template.path.display(), template.entrypoint.display())
+        }),
+    );
+    field("path", Some(package.path.display().to_string()));
+    field("size", Some(format_size(size)));
+
+    let mode = match package::link_target(&package.path) {
+        Some(target) => format!("link -> {}", target.display()),
+        None => metadata
+            .as_ref()
+            .and_then(|metadata| metadata.mode)
+            .unwrap_or(Mode::Copy)
+            .to_string(),
+    };
+    field("mode", Some(mode));
+
+    if let Some(metadata) = &metadata {
+        match &metadata.source {
+            Source::Path { path } | Source::Archive { path } => {
+                field(
+                    "source",
+                    Some(format!("{} {}", metadata.source.kind(), path.display())),
+                );
+            }
+            Source::Url { url, sha256 } => {
+                field("source", Some(format!("url {url}")));
+                field("sha256", Some(sha256.clone()));
+            }
+            Source::Git { url, reference, commit, subdir } => {
+                field("source", Some(format!("git {url}")));
+                field("reference", reference.as_ref().map(ToString::to_string));
+                field("commit", Some(commit.clone()));
+                field(
+                    "subdir",
+                    subdir.as_ref().map(|subdir| subdir.display().to_string()),
+                );
+            }
+        }
+
+        field("installed", metadata.installed.as_ref().map(ToString::to_string));
+        field("typven", metadata.typven.clone());
+    }
+
+    println!("@{namespace}/{package}");
+    for (key, value) in fields {
+        println!("  {key:<12} {value}");
+    }
+
+    Ok(())
+}
+
+/// Formats a size in bytes for humans, such as `12.3 KiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
 /// Clean the local package directory.
 ///
-/// There are a few possible ways a clean is performed (in order):
-/// 1. If a valid package `name` is present clean all versions of that package.
-/// 2. If a valid package `name` is present, as well as a valid package `version`,
-/// clean that target version of that package.
-/// 3. If there is no target package to clean, clean the /local directory of
-/// every package leaving /local empty.
+/// Cleans the selected namespace, or every namespace at once. Every package
+/// version that matches any of the [specs](crate::filter) is cleaned, or every
+/// package version in the namespace if there are none.
+///
+/// Only `{name}/{version}` directories are cleaned, so folders the compiler
+/// would not recognize as packages are left alone. Retention policies keep
+/// some of the selected versions: `--keep-latest` the newest ones of every
+/// package, `--older-than` the ones installed recently. A version is kept when
+/// any policy keeps it.
 ///
-/// When cleaning the entire /local directory it will start searching for
-/// packages to clean 1 level deep so invalid top-level packages that the
-/// compiler would not recognize are not cleaned.
+/// The exact versions that will be removed and how much space that frees are
+/// listed first. With
+/// `--dry-run` nothing else happens, otherwise the removal is confirmed unless
+/// `--yes` is passed.
 ///
 /// # Errors
 ///
-/// A package with `name` and `version` does not exist.
-/// A package with `name` does not exist.
-/// The local package directory is empty.
+/// A spec does not match any installed package version.
+/// The namespace directory is empty.
+/// Some of the package versions could not be removed.
+/// Stdin is not a terminal to confirm on and `--yes` is not passed, which is a
+/// usage error.
 pub fn clean(command: CleanCommand) -> anyhow::Result<()> {
-    let root_dir = dirs::data_dir()
-        .context("failed to locate data directory")?
-        .join("typst/packages/local");
+    let specs = command.filters()?;
+    let root_dir = package::local_dir()?;
 
-    print_cleaning(&command).unwrap();
+    print_cleaning(&command, &specs).unwrap();
 
-    if let Some(name) = command.name {
-        if let Some(version) = command.version {
-            let dir = root_dir.join(format!("{name}/{version}"));
+    let mut selected = Vec::new();
+    let mut matched = vec![false; specs.len()];
+    for namespace in target_namespaces(&command.namespace)? {
+        for package in package::installed(&root_dir.join(&namespace)) {
+            let mut matches = specs.is_empty();
+            for (spec, matched) in specs.iter().zip(&mut matched) {
+                if spec.matches(&package.name, &package.version) {
+                    *matched = true;
+                    matches = true;
+                }
+            }
 
-            return fs::remove_dir_all(dir).with_context(|| {
-                format!("failed to clean {name}:{version}, package not found")
-            });
+            if matches {
+                selected.push(Removal::new(&namespace, package));
+            }
         }
+    }
+
+    // Nothing is removed unless every spec is found, as a spec that matches
+    // nothing is likely a typo.
+    let unmatched: Vec<_> = specs
+        .iter()
+        .zip(&matched)
+        .filter(|(_, matched)| !**matched)
+        .map(|(spec, _)| format!("`{spec}`"))
+        .collect();
+    if !unmatched.is_empty() {
+        bail!(Error::NotFound(format!(
+            "failed to clean {}, no installed package matches",
+            unmatched.join(", ")
+        )));
+    }
 
-        let dir = root_dir.join(&name);
+    if selected.is_empty() {
+        bail!(Error::NotFound("nothing to clean".into()));
+    }
 
-        return fs::remove_dir_all(dir).with_context(|| {
-            format!("failed to clean {name}, package bundle not found")
-        });
+    let removals = retain(selected, &command);
+    if removals.is_empty() {
+        print_note("nothing to clean, every selected version is kept").unwrap();
+        return Ok(());
     }
 
-    let local_package_dirs: Vec<_> = WalkDir::new(&root_dir)
-        .min_depth(1)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.path().is_dir())
-        .collect();
+    for removal in &removals {
+        println!("{removal}");
+    }
 
-    if local_package_dirs.is_empty() {
-        bail!("nothing to clean");
+    let count = removals.len();
+    let size: u64 = removals.iter().map(|removal| removal.size).sum();
+    let summary = |count: usize, size: u64| match command.no_trash {
+        true => format!("{count} package version(s), freeing {}", format_size(size)),
+        false => {
+            format!("{count} package version(s), {}, to the trash", format_size(size))
+        }
+    };
+    let (action, done) = match command.no_trash {
+        true => ("remove", "removed"),
+        false => ("move", "moved"),
+    };
+
+    if command.dry_run {
+        print_summary(&format!("would {action}"), &summary(count, size)).unwrap();
+        return Ok(());
+    }
+
+    let question = match command.no_trash {
+        true => format!("remove {count} package version(s) for good?"),
+        false => format!("move {count} package version(s) to the trash?"),
+    };
+    if !command.yes {
+        if !io::stdin().is_terminal() {
+            bail!(cli::usage_error(
+                "clean",
+                ErrorKind::MissingRequiredArgument,
+                "stdin is not a terminal to confirm on, pass --yes to clean anyway",
+            ));
+        }
+
+        if !confirm(&question)? {
+            print_note("nothing was removed").unwrap();
+            return Ok(());
+        }
+    }
+
+    let expired = trash::expire()?;
+    if expired > 0 {
+        let note = format!("removed {expired} expired package version(s) from the trash");
+        print_note(&note).unwrap();
     }
 
-    for dir in local_package_dirs {
-        let _ = fs::remove_dir_all(dir.path());
+    let mut cleaned = 0;
+    let mut failed = Vec::new();
+    for removal in removals {
+        match removal.remove(!command.no_trash) {
+            Ok(()) => cleaned += removal.size,
+            Err(err) => failed
+                .push(format!("@{}/{}: {err:#}", removal.namespace, removal.package)),
+        }
+    }
+
+    print_summary(done, &summary(count - failed.len(), cleaned)).unwrap();
+
+    if !failed.is_empty() {
+        bail!(Error::Io(format!(
+            "failed to clean {} package version(s):\n  {}",
+            failed.len(),
+            failed.join("\n  ")
+        )));
     }
 
     Ok(())
 }
 
+/// A package version that `clean` selected for removal.
+#[derive(Debug)]
+struct Removal {
+    namespace: String,
+    package: Installed,
+    /// The bytes removing it frees, nothing for linked packages.
+    size: u64,
+    /// When it was installed, in seconds since the Unix epoch.
+    installed: Option<i64>,
+}
+
+impl Removal {
+    fn new(namespace: &str, package: Installed) -> Removal {
+        let linked = package::link_target(&package.path).is_some();
+        let size = match linked {
+            true => 0,
+            false => package::size(&package.path).unwrap_or(0),
+        };
+
+        // Packages installed by hand have no metadata, the time their directory
+        // was last modified is the best guess then.
+        let installed = metadata::read(namespace, &package.name, &package.version)
+            .and_then(|metadata| metadata.installed)
+            .and_then(|installed| time::to_unix(&installed))
+            .or_else(|| {
+                let modified = package.path.symlink_metadata().ok()?.modified().ok()?;
+                Some(time::unix(modified))
+            });
+
+        Removal {
+            namespace: namespace.to_owned(),
+            package,
+            size,
+            installed,
+        }
+    }
+
+    /// Moves the package version and its metadata to the trash, or removes
+    /// them for good unless `trash`, and removes the package bundle when no
+    /// other version is left in it.
+    fn remove(&self, trash: bool) -> anyhow::Result<()> {
+        if trash {
+            return trash::add(&self.namespace, &self.package);
+        }
+
+        let path = &self.package.path;
+        package::remove_dir(path)
+            .with_context(|| format!("failed to remove {}", path.display()))?;
+        metadata::remove(
+            &self.namespace,
+            &self.package.name,
+            Some(&self.package.version),
+        )?;
+
+        // Only succeeds when the bundle is empty.
+        fs::remove_dir(path.parent().unwrap()).unwrap_or(());
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Removal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}/{}", self.namespace, self.package)?;
+
+        match package::link_target(&self.package.path) {
+            Some(target) => write!(f, " (link -> {})", target.display()),
+            None => write!(f, " ({})", format_size(self.size)),
+        }
+    }
+}
+
+/// The `selected` package versions that the retention policies of `command`
+/// do not keep.
+fn retain(mut selected: Vec<Removal>, command: &CleanCommand) -> Vec<Removal> {
+    // Newest first, so the first versions of every package are the latest.
+    selected.sort_by(|a, b| {
+        (&a.namespace, &a.package.name, Reverse(&a.package.version)).cmp(&(
+            &b.namespace,
+            &b.package.name,
+            Reverse(&b.package.version),
+        ))
+    });
+
+    let cutoff = command.older_than.map(time::ago);
+
+    let mut newer = 0;
+    let mut previous: Option<(String, String)> = None;
+    selected
+        .into_iter()
+        .filter(|removal| {
+            let key = (removal.namespace.clone(), removal.package.name.clone());
+            match previous.as_ref() == Some(&key) {
+                true => newer += 1,
+                false => newer = 0,
+            }
+            previous = Some(key);
+
+            let latest = command.keep_latest.is_some_and(|keep| newer < keep);
+            let recent = cutoff.is_some_and(|cutoff| {
+                removal.installed.is_none_or(|installed| installed > cutoff)
+            });
+
+            !latest && !recent
+        })
+        .collect()
+}
+
+/// Asks the user on stdin whether to go on, where anything but yes is no.
+pub fn confirm(question: &str) -> anyhow::Result<bool> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_warning)?;
+    write!(w, "{question}")?;
+    w.reset()?;
+    write!(w, " [y/N] ")?;
+    w.flush()?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .context("failed to read the answer from stdin")?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// The namespaces selected by `args`: either the given namespace or every
+/// namespace in the local package directory.
+pub fn target_namespaces(args: &NamespaceArgs) -> anyhow::Result<Vec<String>> {
+    if args.all_namespaces {
+        return package::namespaces();
+    }
+
+    Ok(vec![args.namespace.clone()])
+}
+
+/// Print a note.
+pub fn print_note(note: &str) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_note)?;
+    write!(w, "note")?;
+
+    w.reset()?;
+    writeln!(w, ": {note}")
+}
+
+/// Print the outcome of a clean or prune.
+pub fn print_summary(action: &str, summary: &str) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(&styles.header_help)?;
+    write!(w, "{action}")?;
+
+    w.reset()?;
+    writeln!(w, " {summary}")
+}
+
 /// Print that a clean is happening.
-fn print_cleaning(command: &CleanCommand) -> io::Result<()> {
+fn print_cleaning(command: &CleanCommand, specs: &[Filter]) -> io::Result<()> {
     let mut w = color_stream();
     let styles = term::Styles::default();
 
     w.set_color(&styles.header_help)?;
     write!(w, "cleaning")?;
 
-    if let Some(name) = &command.name {
-        if let Some(version) = &command.version {
-            w.reset()?;
-            return writeln!(w, " {name}:{version}");
+    let namespace = match command.namespace.all_namespaces {
+        true => "*",
+        false => command.namespace.namespace.as_str(),
+    };
+
+    w.reset()?;
+    match specs.is_empty() {
+        true => writeln!(w, " all in @{namespace}"),
+        false => {
+            let specs: Vec<_> = specs.iter().map(ToString::to_string).collect();
+            writeln!(w, " {} in @{namespace}", specs.join(", "))
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-        w.reset()?;
-        return writeln!(w, " {name}");
+    use clap::Parser;
+
+    use crate::{error, metadata::Metadata, testing};
+
+    use super::*;
+
+    /// A removal of `namespace/name:version`, installed `age` days ago.
+    fn removal(package: &str, age: Option<u64>) -> Removal {
+        let (namespace, package) = package.split_once('/').unwrap();
+        let (name, version) = package.split_once(':').unwrap();
+
+        Removal {
+            namespace: namespace.into(),
+            package: Installed {
+                path: PathBuf::from(format!("{namespace}/{name}/{version}")),
+                name: name.into(),
+                version: version.parse().unwrap(),
+            },
+            size: 0,
+            installed: age.map(|age| time::ago(Duration::from_secs(age * 86_400))),
+        }
     }
 
-    w.reset()?;
-    writeln!(w, " all")
+    /// The package versions `clean` with `args` removes of `selected`.
+    fn removed(args: &[&str], selected: Vec<Removal>) -> Vec<String> {
+        let command = CleanCommand::try_parse_from(["clean"].iter().chain(args)).unwrap();
+        retain(selected, &command)
+            .iter()
+            .map(|removal| format!("{}/{}", removal.namespace, removal.package))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_latest_versions_of_every_package() {
+        let selected = || {
+            [
+                "local/a:1.2.0",
+                "acme/a:1.0.0",
+                "local/a:1.10.0",
+                "local/b:0.1.0",
+                "local/a:2.0.0-beta.1",
+                "acme/a:2.0.0",
+                "local/a:1.0.0",
+            ]
+            .into_iter()
+            .map(|package| removal(package, None))
+            .collect()
+        };
+
+        assert_eq!(
+            removed(&["--keep-latest", "2"], selected()),
+            ["local/a:1.2.0", "local/a:1.0.0"]
+        );
+        assert_eq!(
+            removed(&["--keep-latest", "1"], selected()),
+            ["acme/a:1.0.0", "local/a:1.10.0", "local/a:1.2.0", "local/a:1.0.0"]
+        );
+        assert_eq!(removed(&["--keep-latest", "0"], selected()).len(), 7);
+        assert_eq!(removed(&["--keep-latest", "3"], selected()), ["local/a:1.0.0"]);
+    }
+
+    #[test]
+    fn keeps_recently_installed_versions() {
+        let selected = || {
+            vec![
+                removal("local/a:1.0.0", Some(30)),
+                removal("local/a:2.0.0", Some(10)),
+                removal("local/a:3.0.0", Some(1)),
+                removal("local/b:1.0.0", None),
+            ]
+        };
+
+        assert_eq!(
+            removed(&["--older-than", "7d"], selected()),
+            ["local/a:2.0.0", "local/a:1.0.0"]
+        );
+        assert_eq!(
+            removed(&["--older-than", "7d", "--keep-latest", "2"], selected()),
+            ["local/a:1.0.0"]
+        );
+        let forever = ["--older-than", "9223372036854775807s"];
+        assert!(removed(&forever, selected()).is_empty());
+    }
+
+    #[test]
+    fn requires_yes_without_a_terminal() {
+        if io::stdin().is_terminal() {
+            return;
+        }
+
+        let data = testing::data_dir();
+        let dir = data.packages().join("local/mypkg/0.1.0");
+        testing::write_package(&dir, "mypkg", "0.1.0", "");
+
+        let command = CleanCommand::try_parse_from(["clean", "mypkg"]).unwrap();
+        let err = clean(command).unwrap_err();
+        assert_eq!(error::exit_code(&err), error::EXIT_USAGE);
+        assert!(dir.is_dir());
+
+        let command = CleanCommand::try_parse_from(["clean", "mypkg", "--yes"]).unwrap();
+        clean(command).unwrap();
+        assert!(!dir.exists());
+        assert_eq!(trash::entries().unwrap().len(), 1);
+    }
+
+    /// The packages `ls` with `args` lists, one `@namespace/name:version` per
+    /// version.
+    fn listed(args: &[&str]) -> Vec<String> {
+        let command = LsCommand::try_parse_from(["ls"].iter().chain(args)).unwrap();
+        let packages = list(&command).unwrap();
+        render(&packages, OutputFormat::Plain, false)
+            .unwrap()
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn lists_packages_in_every_format() {
+        let data = testing::data_dir();
+        let dir = data.packages().join("local/a/1.2.0");
+        testing::write_package(&dir, "a", "1.2.0", "");
+        fs::write(
+            dir.join("typst.toml"),
+            "[package]\nname = \"a\"\nversion = \"1.2.0\"\nentrypoint = \"lib.typ\"\n\
+             [template]\npath = \"template\"\nentrypoint = \"main.typ\"\n\
+             [tool.mytool]\nkey = \"value\"\n",
+        )
+        .unwrap();
+        let metadata = Metadata {
+            mode: Some(Mode::Copy),
+            installed: Some("2024-05-01T12:00:00Z".parse().unwrap()),
+            typven: None,
+            source: Source::Path { path: "/src/a".into() },
+            files: BTreeMap::new(),
+        };
+        metadata::write("local", "a", &Version::new(1, 2, 0), &metadata).unwrap();
+        testing::write_package(&data.packages().join("local/b/0.1.0"), "b", "0.1.0", "");
+        testing::write_package(&data.packages().join("acme/c/1.0.0"), "c", "1.0.0", "");
+
+        let command = LsCommand::try_parse_from(["ls"]).unwrap();
+        let packages = list(&command).unwrap();
+
+        let plain = render(&packages, OutputFormat::Plain, false).unwrap();
+        assert_eq!(plain, "@local/a:1.2.0\n@local/b:0.1.0\n");
+
+        let json = render(&packages, OutputFormat::Json, false).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let a = &json[0]["versions"][0];
+        assert_eq!(json[0]["name"], "a");
+        assert_eq!(a["version"], "1.2.0");
+        assert_eq!(a["installed"], "2024-05-01T12:00:00Z");
+        assert_eq!(a["source"], serde_json::json!({ "type": "path", "path": "/src/a" }));
+        assert_eq!(a["manifest"]["template"]["entrypoint"], "main.typ");
+        assert_eq!(a["manifest"]["tool"]["mytool"]["key"], "value");
+        assert!(json[1]["versions"][0].get("installed").is_none());
+
+        // The manifest tables come before `installed` in a listed version.
+        let toml = render(&packages, OutputFormat::Toml, false).unwrap();
+        let toml: toml::Table = toml::from_str(&toml).unwrap();
+        let a = &toml["package"][0]["versions"][0];
+        assert_eq!(a["installed"].as_str(), Some("2024-05-01T12:00:00Z"));
+        assert_eq!(a["manifest"]["template"]["path"].as_str(), Some("template"));
+        assert_eq!(a["manifest"]["tool"]["mytool"]["key"].as_str(), Some("value"));
+        assert_eq!(toml["package"][1]["name"].as_str(), Some("b"));
+
+        let table = render(&packages, OutputFormat::Table, false).unwrap();
+        assert!(table.contains("path") && table.contains("2024-05-01"), "{table}");
+        assert!(!table.contains("namespace") && !table.contains("acme"), "{table}");
+
+        let command = LsCommand::try_parse_from(["ls", "--all-namespaces"]).unwrap();
+        let packages = list(&command).unwrap();
+        let table = render(&packages, OutputFormat::Table, true).unwrap();
+        assert!(table.contains("namespace") && table.contains("acme"), "{table}");
+        assert_eq!(
+            listed(&["--all-namespaces"]),
+            ["@acme/c:1.0.0", "@local/a:1.2.0", "@local/b:0.1.0"]
+        );
+        assert_eq!(listed(&["--namespace", "acme", "c:1.0.0"]), ["@acme/c:1.0.0"]);
+
+        let empty = render(&[], OutputFormat::Toml, false).unwrap();
+        assert_eq!(
+            toml::from_str::<toml::Table>(&empty).unwrap()["package"]
+                .as_array()
+                .map(Vec::len),
+            Some(0)
+        );
+        assert_eq!(render(&[], OutputFormat::Json, false).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn sorts_packages_and_versions() {
+        let data = testing::data_dir();
+        let install = |namespace: &str, name: &str, version: &str, installed: &str| {
+            let dir = data.packages().join(format!("{namespace}/{name}/{version}"));
+            testing::write_package(&dir, name, version, "");
+            let metadata = Metadata {
+                mode: Some(Mode::Copy),
+                installed: Some(installed.parse().unwrap()),
+                typven: None,
+                source: Source::Path { path: dir.clone() },
+                files: BTreeMap::new(),
+            };
+            metadata::write(namespace, name, &version.parse().unwrap(), &metadata)
+                .unwrap();
+            dir
+        };
+
+        install("local", "a", "1.2.0", "2024-01-01T00:00:00Z");
+        install("local", "a", "1.10.0", "2024-01-02T00:00:00Z");
+        install("local", "a", "1.9.0", "2024-01-03T00:00:00Z");
+        install("local", "a", "1.10.0-rc.1", "2024-01-04T00:00:00Z");
+        let b = install("local", "b", "0.1.0", "2024-03-01T00:00:00Z");
+        fs::write(b.join("big.bin"), vec![0; 64 * 1024]).unwrap();
+        install("local", "c", "2.0.0", "2024-02-01T00:00:00Z");
+        install("local", "c", "1.0.0", "2024-01-01T00:00:00Z");
+        install("acme", "a", "3.0.0", "2024-06-01T00:00:00Z");
+
+        // Versions sort by semver, not as strings, and pre-releases come
+        // before their release.
+        assert_eq!(
+            listed(&["a"]),
+            [
+                "@local/a:1.10.0",
+                "@local/a:1.10.0-rc.1",
+                "@local/a:1.9.0",
+                "@local/a:1.2.0"
+            ]
+        );
+        assert_eq!(
+            listed(&["--latest"]),
+            ["@local/a:1.10.0", "@local/b:0.1.0", "@local/c:2.0.0"]
+        );
+        assert_eq!(listed(&["a@<1.10"]), ["@local/a:1.9.0", "@local/a:1.2.0"]);
+
+        // The packages in the order `ls` with `args` lists them.
+        let order = |args: &[&str]| {
+            let mut packages: Vec<_> = listed(args)
+                .iter()
+                .map(|listed| listed.split(':').next().unwrap().to_owned())
+                .collect();
+            packages.dedup();
+            packages
+        };
+
+        assert_eq!(order(&["--sort", "name"]), ["@local/a", "@local/b", "@local/c"]);
+        assert_eq!(order(&["--sort", "versions"]), ["@local/a", "@local/c", "@local/b"]);
+        assert_eq!(order(&["--sort", "size"]), ["@local/b", "@local/a", "@local/c"]);
+        assert_eq!(order(&["--sort", "installed"]), ["@local/b", "@local/c", "@local/a"]);
+        assert_eq!(
+            order(&["--sort", "installed", "--all-namespaces"]),
+            ["@acme/a", "@local/b", "@local/c", "@local/a"]
+        );
+    }
 }
@@ -0,0 +1,370 @@
+//! Verifying installed packages against the file hashes recorded in their
+//! [metadata](crate::metadata).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Context};
+use codespan_reporting::term::{self, termcolor::WriteColor};
+use url::Url;
+
+use crate::{
+    cli::{InstallCommand, VerifyCommand},
+    color_stream,
+    error::Error,
+    hash,
+    install::{self, Transaction},
+    metadata::{self, Source},
+    package::{self, Installed},
+    util,
+};
+
+/// Verifies that installed packages contain exactly the files they were
+/// installed with.
+///
+/// Every file is hashed again and compared to the hashes recorded when the
+/// package was installed, reporting the files that were modified, added or
+/// went missing. With `--restore`, packages that do not match are reinstalled
+/// from the source they were installed from. Linked packages are skipped, as
+/// are packages without recorded hashes, such as packages installed by hand.
+///
+/// # Errors
+///
+/// When no package is installed that matches, or a package does not match and
+/// is not restored.
+pub fn verify(command: VerifyCommand) -> anyhow::Result<()> {
+    let root_dir = package::local_dir()?;
+
+    let mut packages = Vec::new();
+    for namespace in util::target_namespaces(&command.namespace)? {
+        for package in package::installed(&root_dir.join(&namespace)) {
+            if let Some(arg) = &command.package {
+                if package.name != arg.name
+                    || arg.version.as_ref().is_some_and(|v| *v != package.version)
+                {
+                    continue;
+                }
+            }
+
+            packages.push((namespace.clone(), package));
+        }
+    }
+
+    if packages.is_empty() {
+        bail!(Error::NotFound(match &command.package {
+            Some(arg) => match &arg.version {
+                Some(version) => format!("{}:{version} is not installed", arg.name),
+                None => format!("{} is not installed", arg.name),
+            },
+            None => "no valid packages found".into(),
+        }));
+    }
+
+    let mut summary = Summary::default();
+    for (namespace, package) in packages {
+        match check(&namespace, &package)? {
+            Status::Intact => {
+                print_status("verified", false, &namespace, &package, "").unwrap();
+                summary.verified += 1;
+            }
+            Status::Modified(diff, source) => {
+                print_status("modified", true, &namespace, &package, "").unwrap();
+                diff.print().unwrap();
+                summary.modified += 1;
+
+                if command.restore {
+                    restore(&namespace, &package, source)?;
+                    summary.restored += 1;
+                }
+            }
+            Status::Unrecorded => {
+                let note = " (no file hashes recorded)";
+                print_status("unverified", true, &namespace, &package, note).unwrap();
+                summary.unverified += 1;
+            }
+            Status::Linked(target) => {
+                let note = format!(" (linked to {})", target.display());
+                print_status("skipped", false, &namespace, &package, &note).unwrap();
+                summary.skipped += 1;
+            }
+        }
+    }
+
+    summary.print()?;
+
+    let unrestored = summary.modified - summary.restored;
+    if unrestored > 0 {
+        bail!(Error::InvalidPackage(format!(
+            "{unrestored} package(s) do not match the files they were installed with \
+             (use --restore to reinstall them)"
+        )));
+    }
+
+    Ok(())
+}
+
+/// How an installed package compares to what was installed.
+#[derive(Debug)]
+enum Status {
+    /// Every file is exactly as it was installed.
+    Intact,
+    /// Files were modified, added or removed since the package was installed
+    /// from `source`.
+    Modified(Diff, Source),
+    /// The package has no recorded file hashes to verify against.
+    Unrecorded,
+    /// The package is linked to its source, which is expected to change.
+    Linked(PathBuf),
+}
+
+/// The files that differ from what was installed, by their path relative to
+/// the package root.
+#[derive(Debug, Default)]
+struct Diff {
+    modified: Vec<String>,
+    added: Vec<String>,
+    missing: Vec<String>,
+}
+
+impl Diff {
+    /// Compares the `current` hashes of the files of a package to the ones
+    /// `recorded` when it was installed, both by path.
+    fn new(
+        recorded: &BTreeMap<String, String>,
+        current: &BTreeMap<String, String>,
+    ) -> Diff {
+        let mut diff = Diff::default();
+
+        let paths: BTreeSet<&String> = recorded.keys().chain(current.keys()).collect();
+        for path in paths {
+            match (recorded.get(path), current.get(path)) {
+                (Some(recorded), Some(actual)) if recorded != actual => {
+                    diff.modified.push(path.clone())
+                }
+                (Some(_), None) => diff.missing.push(path.clone()),
+                (None, Some(_)) => diff.added.push(path.clone()),
+                _ => {}
+            }
+        }
+
+        diff
+    }
+
+    /// Whether no file differs.
+    fn is_empty(&self) -> bool {
+        self.modified.is_empty() && self.added.is_empty() && self.missing.is_empty()
+    }
+
+    /// Print every file that differs.
+    fn print(&self) -> io::Result<()> {
+        let mut w = color_stream();
+
+        for (change, files) in [
+            ("modified", &self.modified),
+            ("added", &self.added),
+            ("missing", &self.missing),
+        ] {
+            for file in files {
+                writeln!(w, "  {change} {file}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares the installed `package` to the file hashes in its metadata.
+fn check(namespace: &str, package: &Installed) -> anyhow::Result<Status> {
+    if let Some(target) = package::link_target(&package.path) {
+        return Ok(Status::Linked(target));
+    }
+
+    let Some(metadata) = metadata::read(namespace, &package.name, &package.version)
+        .filter(|metadata| !metadata.files.is_empty())
+    else {
+        return Ok(Status::Unrecorded);
+    };
+
+    let current = hash::each(&package.path)
+        .with_context(|| format!("failed to hash {}", package.path.display()))?;
+
+    let diff = Diff::new(&metadata.files, &current);
+    match diff.is_empty() {
+        true => Ok(Status::Intact),
+        false => Ok(Status::Modified(diff, metadata.source)),
+    }
+}
+
+/// Reinstalls `package` from the `source` it was installed from.
+///
+/// Packages installed from git are restored from the exact commit they were
+/// installed from. Verification is skipped, as the package is restored to what
+/// was installed before.
+///
+/// # Errors
+///
+/// When the source can not be fetched or no longer holds the package.
+fn restore(namespace: &str, package: &Installed, source: Source) -> anyhow::Result<()> {
+    let mut command = InstallCommand {
+        namespace: namespace.to_owned(),
+        force: true,
+        no_verify: true,
+        ..InstallCommand::default()
+    };
+
+    match &source {
+        Source::Path { path } | Source::Archive { path } => {
+            command.path = Some(path.clone())
+        }
+        Source::Url { url, sha256 } => {
+            command.url = Some(Url::parse(url).context("invalid url in metadata")?);
+            command.sha256 = Some(sha256.clone());
+        }
+        Source::Git { url, commit, subdir, .. } => {
+            command.git = Some(Url::parse(url).context("invalid url in metadata")?);
+            command.rev = Some(commit.clone());
+            command.subdir = subdir.clone();
+        }
+    }
+
+    let (_origin, path) = install::resolve(&command)?;
+    let Some(restored) = install::find(&path)?
+        .into_iter()
+        .find(|p| p.name == package.name && p.version == package.version)
+    else {
+        bail!(Error::NotFound(format!(
+            "can not restore @{namespace}/{package}, its source no longer holds it"
+        )));
+    };
+
+    let mut transaction = Transaction::default();
+    install::install(restored, source, &command, &mut transaction)?;
+    transaction.commit();
+
+    Ok(())
+}
+
+/// Counts the statuses of the verified packages.
+#[derive(Debug, Default)]
+struct Summary {
+    verified: usize,
+    modified: usize,
+    restored: usize,
+    unverified: usize,
+    skipped: usize,
+}
+
+impl Summary {
+    /// Print the summary.
+    fn print(&self) -> io::Result<()> {
+        let mut w = color_stream();
+        let styles = term::Styles::default();
+
+        w.set_color(&styles.header_help)?;
+        write!(w, "summary")?;
+
+        w.reset()?;
+        writeln!(
+            w,
+            ": {} verified, {} modified, {} restored, {} unverified, {} skipped",
+            self.verified, self.modified, self.restored, self.unverified, self.skipped
+        )
+    }
+}
+
+/// Print the status of a package, with an optional `note` after it.
+fn print_status(
+    status: &str,
+    warning: bool,
+    namespace: &str,
+    package: &Installed,
+    note: &str,
+) -> io::Result<()> {
+    let mut w = color_stream();
+    let styles = term::Styles::default();
+
+    w.set_color(match warning {
+        true => &styles.header_warning,
+        false => &styles.header_help,
+    })?;
+    write!(w, "{status}")?;
+
+    w.reset()?;
+    writeln!(w, " @{namespace}/{package}{note}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{cli::NamespaceArgs, testing};
+
+    use super::*;
+
+    #[test]
+    fn diffs_file_hashes() {
+        let hashes = |files: &[(&str, &str)]| -> BTreeMap<String, String> {
+            files.iter().map(|&(path, hash)| (path.into(), hash.into())).collect()
+        };
+
+        let recorded =
+            hashes(&[("lib.typ", "a"), ("typst.toml", "b"), ("util.typ", "c")]);
+        let current = hashes(&[("lib.typ", "a"), ("typst.toml", "x"), ("new.typ", "d")]);
+
+        let diff = Diff::new(&recorded, &current);
+        assert_eq!(diff.modified, ["typst.toml"]);
+        assert_eq!(diff.added, ["new.typ"]);
+        assert_eq!(diff.missing, ["util.typ"]);
+        assert!(!diff.is_empty());
+
+        assert!(Diff::new(&recorded, &recorded).is_empty());
+        assert!(Diff::new(&BTreeMap::new(), &BTreeMap::new()).is_empty());
+        assert_eq!(Diff::new(&BTreeMap::new(), &current).added.len(), 3);
+        assert_eq!(Diff::new(&recorded, &BTreeMap::new()).missing.len(), 3);
+    }
+
+    #[test]
+    fn restores_git_packages_from_recorded_commit() {
+        let data = testing::data_dir();
+        let repo = testing::repo();
+
+        install::packages(InstallCommand {
+            git: Some(repo.url.clone()),
+            tag: Some("v0.1.0".into()),
+            namespace: "local".into(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let dir = data.packages().join("local/mypkg/0.1.0");
+        fs::write(dir.join("lib.typ"), "#let version = \"edited\"\n").unwrap();
+        fs::write(dir.join("added.typ"), "").unwrap();
+
+        let command = |restore| VerifyCommand {
+            package: None,
+            restore,
+            namespace: NamespaceArgs { namespace: "local".into(), all_namespaces: false },
+        };
+        let err = verify(command(false)).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::InvalidPackage(_))), "{err:#}");
+
+        // `main` moved on to 0.2.0 since, only the recorded commit has 0.1.0.
+        verify(command(true)).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("lib.typ")).unwrap(),
+            "#let version = \"0.1.0\"\n"
+        );
+        assert!(!dir.join("added.typ").exists());
+
+        let metadata =
+            metadata::read("local", "mypkg", &"0.1.0".parse().unwrap()).unwrap();
+        match metadata.source {
+            Source::Git { commit, .. } => assert_eq!(commit, repo.commits[0]),
+            source => panic!("unexpected source {source:?}"),
+        }
+        verify(command(false)).unwrap();
+    }
+}